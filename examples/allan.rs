@@ -0,0 +1,167 @@
+//! Allan deviation of all six axes, to characterise sensor noise for Kalman filter tuning.
+//!
+//! Record a long stationary run (hours give the best bias instability estimate) and analyse it:
+//! ```bash
+//! $ allan record samples.csv 7200 100   # file, seconds, sample rate in Hz
+//! $ allan analyse samples.csv allan.csv
+//! ```
+//! `record` writes `t,ax,ay,az,gx,gy,gz` lines in s, g and rad/s. `analyse` writes
+//! `tau,ax,ay,az,gx,gy,gz` Allan deviations for plotting and prints the fitted noise parameters:
+//! * white noise N (angle/velocity random walk): deviation at tau = 1 s on the -1/2 slope
+//! * bias instability B: minimum deviation / 0.664
+//! * rate random walk K: deviation at tau = 3 s on the +1/2 slope
+//!
+//! Source: IEEE Std 952-1997, Annex C
+
+use mpu6050::*;
+use linux_embedded_hal::{I2cdev, Delay};
+use embedded_hal::delay::DelayNs;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::Instant;
+
+const AXES: [&str; 6] = ["ax", "ay", "az", "gx", "gy", "gz"];
+
+/// Sample period in s and one column of samples per axis
+type Log = (f64, Vec<Vec<f64>>);
+
+fn record(path: &str, seconds: f64, rate: f64) -> Result<(), Box<dyn Error>> {
+    let i2c = I2cdev::new("/dev/i2c-1")?;
+    let mut delay = Delay;
+    let mut mpu = Mpu6050::new(i2c);
+    mpu.init(&mut delay).map_err(|e| format!("{:?}", e))?;
+
+    let mut out = BufWriter::new(File::create(path)?);
+    let start = Instant::now();
+    let period = 1. / rate;
+    let mut next = 0.;
+
+    while next < seconds {
+        let acc = mpu.get_acc_uncorrected().map_err(|e| format!("{:?}", e))?;
+        let gyro = mpu.get_gyro_uncorrected().map_err(|e| format!("{:?}", e))?;
+        writeln!(out, "{:.6},{},{},{},{},{},{}", next, acc.x, acc.y, acc.z, gyro.x, gyro.y, gyro.z)?;
+
+        next += period;
+        let now = start.elapsed().as_secs_f64();
+        if next > now {
+            delay.delay_us(((next - now) * 1e6) as u32);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a log written by `record`: sample period and one column per axis
+fn read_log(path: &str) -> Result<Log, Box<dyn Error>> {
+    let mut time = Vec::new();
+    let mut columns = vec![Vec::new(); AXES.len()];
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let values = line.split(',').map(|v| v.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>()?;
+        if values.len() != AXES.len() + 1 {
+            return Err(format!("expected {} columns: {}", AXES.len() + 1, line).into());
+        }
+        time.push(values[0]);
+        for (column, value) in columns.iter_mut().zip(&values[1..]) {
+            column.push(*value);
+        }
+    }
+
+    if time.len() < 3 {
+        return Err("log too short".into());
+    }
+    let period = (time[time.len() - 1] - time[0]) / (time.len() - 1) as f64;
+    Ok((period, columns))
+}
+
+/// Cluster sizes, roughly logarithmically spaced up to a third of the run
+fn cluster_sizes(n: usize) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    let mut m = 1.;
+    while (m as usize) <= n / 3 {
+        if sizes.last() != Some(&(m as usize)) {
+            sizes.push(m as usize);
+        }
+        m *= 1.25;
+    }
+    sizes
+}
+
+/// Overlapping Allan deviation for cluster size m, from the integrated samples theta with period tau0
+fn allan_deviation(theta: &[f64], tau0: f64, m: usize) -> f64 {
+    let n = theta.len() - 1;
+    let tau = m as f64 * tau0;
+    let sum: f64 = (0..n - 2 * m)
+        .map(|k| theta[k + 2 * m] - 2. * theta[k + m] + theta[k])
+        .map(|d| d * d)
+        .sum();
+    (sum / (2. * tau * tau * (n - 2 * m) as f64)).sqrt()
+}
+
+/// Fitted (N, B, K) from log-log curve
+fn fit(taus: &[f64], adev: &[f64]) -> (f64, f64, f64) {
+    let logs: Vec<(f64, f64)> = taus.iter().zip(adev).map(|(t, a)| (t.log10(), a.log10())).collect();
+    let slopes: Vec<f64> = logs.windows(2).map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0)).collect();
+
+    // point whose local slope is closest to the given one, and the line through it evaluated at tau
+    let line_at = |slope: f64, tau: f64| {
+        let i = slopes.iter()
+            .enumerate()
+            .min_by(|a, b| (a.1 - slope).abs().total_cmp(&(b.1 - slope).abs()))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        let (log_tau, log_adev) = logs[i];
+        10f64.powf(log_adev - slope * (log_tau - tau.log10()))
+    };
+
+    let n = line_at(-0.5, 1.);
+    let b = adev.iter().copied().fold(f64::MAX, f64::min) / (2. * 2f64.ln() / std::f64::consts::PI).sqrt();
+    let k = line_at(0.5, 3.);
+    (n, b, k)
+}
+
+fn analyse(path: &str, out_path: &str) -> Result<(), Box<dyn Error>> {
+    let (tau0, columns) = read_log(path)?;
+    let n = columns[0].len();
+    let sizes = cluster_sizes(n);
+    let taus: Vec<f64> = sizes.iter().map(|m| *m as f64 * tau0).collect();
+
+    let curves: Vec<Vec<f64>> = columns.iter().map(|y| {
+        // integrate once, clusters are then differences of the integral
+        let mut theta = Vec::with_capacity(n + 1);
+        theta.push(0.);
+        for value in y {
+            theta.push(theta[theta.len() - 1] + value * tau0);
+        }
+        sizes.iter().map(|m| allan_deviation(&theta, tau0, *m)).collect()
+    }).collect();
+
+    let mut out = BufWriter::new(File::create(out_path)?);
+    writeln!(out, "tau,{}", AXES.join(","))?;
+    for (i, tau) in taus.iter().enumerate() {
+        let row: Vec<String> = curves.iter().map(|c| c[i].to_string()).collect();
+        writeln!(out, "{},{}", tau, row.join(","))?;
+    }
+
+    println!("{} samples, {:.1} Hz, {:.0} s", n, 1. / tau0, n as f64 * tau0);
+    println!("axis  N (white noise, unit/sqrt(Hz))  B (bias instability, unit)  K (rate random walk, unit*sqrt(Hz))");
+    for (axis, curve) in AXES.iter().zip(&curves) {
+        let (n, b, k) = fit(&taus, curve);
+        println!("{:4}  {:<32.3e}  {:<26.3e}  {:.3e}", axis, n, b, k);
+    }
+    println!("units: g for ax..az, rad/s for gx..gz");
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("record") if args.len() == 5 => record(&args[2], args[3].parse()?, args[4].parse()?),
+        Some("analyse") if args.len() == 4 => analyse(&args[2], &args[3]),
+        _ => Err("usage: allan record <log.csv> <seconds> <rate_hz> | allan analyse <log.csv> <allan.csv>".into()),
+    }
+}
@@ -44,19 +44,29 @@ fn main() -> Result<(), Mpu6050Error<LinuxI2CError>> {
 
     // test sleep. Default no, in wake()
     println!("Test sleep");
-    assert_eq!(mpu.get_sleep_enabled().unwrap(), false);
-    mpu.set_sleep_enabled(true).unwrap();
-    assert_eq!(mpu.get_sleep_enabled().unwrap(), true);
-    mpu.set_sleep_enabled(false).unwrap();
-    assert_eq!(mpu.get_sleep_enabled().unwrap(), false);
+    assert!(!mpu.get_sleep_enabled().unwrap());
+    let mut mpu = mpu.into_asleep().unwrap();
+    assert!(mpu.get_sleep_enabled().unwrap());
+    let mut mpu = mpu.into_awake(&mut delay).unwrap();
+    assert!(!mpu.get_sleep_enabled().unwrap());
+
+    // test low power accel: temp sensor off, internal oscillator
+    println!("Test low power accel");
+    let mut mpu = mpu.into_low_power_accel(LP_WAKE_CTRL::_5).unwrap();
+    assert!(!mpu.get_temp_enabled().unwrap());
+    assert!(mpu.get_gyro_standby().unwrap());
+    assert_eq!(mpu.get_clock_source().unwrap(), CLKSEL::OSCILL);
+    let mut mpu = mpu.into_awake(&mut delay).unwrap();
+    assert!(mpu.get_temp_enabled().unwrap());
+    assert_ne!(mpu.get_temp().unwrap(), 36.53);
 
     // test temp enable/disable
     println!("Test temp enable/disable");
     mpu.set_temp_enabled(false).unwrap();
-    assert_eq!(mpu.get_temp_enabled().unwrap(), false);
+    assert!(!mpu.get_temp_enabled().unwrap());
     assert_eq!(mpu.get_temp().unwrap(), 36.53);
     mpu.set_temp_enabled(true).unwrap();
-    assert_eq!(mpu.get_temp_enabled().unwrap(), true);
+    assert!(mpu.get_temp_enabled().unwrap());
     assert_ne!(mpu.get_temp().unwrap(), 36.53);
 
     // Test clksel: GXAXIS per default, set in wake()
@@ -79,12 +89,12 @@ fn main() -> Result<(), Mpu6050Error<LinuxI2CError>> {
 
     // reset
     println!("Test reset");
-    mpu.reset_device(&mut delay).unwrap();
+    let mut mpu = mpu.reset_device(&mut delay).unwrap();
     assert_eq!(mpu.get_accel_hpf().unwrap(), ACCEL_HPF::_RESET);
     assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G2);
     assert_eq!(mpu.get_gyro_range().unwrap(), GyroRange::D250);
-    assert_eq!(mpu.get_sleep_enabled().unwrap(), true);
-    assert_eq!(mpu.get_temp_enabled().unwrap(), true);
+    assert!(mpu.get_sleep_enabled().unwrap());
+    assert!(mpu.get_temp_enabled().unwrap());
 
     println!("Test successful");
     Ok(())
@@ -0,0 +1,329 @@
+//! Full quaternion orientation (AHRS) from 6-axis data, or 9-axis if a magnetometer is present.
+//!
+//! The quaternion rotates vectors from the sensor frame into the world frame (z up).
+//! Accelerometer input is expected in g, gyro in rad/s, magnetometer in any unit
+//! (only the direction is used).
+//!
+//! Sources:
+//! * Madgwick, "An efficient orientation filter for inertial and inertial/magnetic sensor arrays", 2010
+//! * Mahony et al., "Nonlinear Complementary Filters on the Special Orthogonal Group", 2008
+//! * https://x-io.co.uk/open-source-imu-and-ahrs-algorithms/
+
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::i2c::I2c;
+use nalgebra::{Matrix3x4, Quaternion, Rotation3, UnitQuaternion, Vector3, Vector4};
+
+/// Common readout of all orientation estimators
+pub trait Orientation {
+    /// Current estimate, sensor to world frame
+    fn quaternion(&self) -> UnitQuaternion<f32>;
+
+    /// (roll, pitch, yaw) in rad
+    fn euler_angles(&self) -> (f32, f32, f32) {
+        self.quaternion().euler_angles()
+    }
+
+    /// Direction of gravity in the sensor frame, in g. Matches `get_acc` of a sensor at rest
+    fn gravity(&self) -> Vector3<f32> {
+        self.quaternion().inverse_transform_vector(&Vector3::z())
+    }
+
+    /// Rotation matrix, sensor to world frame
+    fn rotation_matrix(&self) -> Rotation3<f32> {
+        self.quaternion().to_rotation_matrix()
+    }
+}
+
+/// Integrates the gyro rate into q for dt seconds, without normalizing
+fn integrate(q: &Quaternion<f32>, gyro: &Vector3<f32>, dt: f32) -> Quaternion<f32> {
+    q * Quaternion::from_imag(*gyro) * (0.5 * dt)
+}
+
+/// Normalized copy of v, None if v is zero
+fn normalized(v: &Vector3<f32>) -> Option<Vector3<f32>> {
+    v.try_normalize(0.)
+}
+
+/// Madgwick gradient descent filter
+#[derive(Copy, Clone, Debug)]
+pub struct Madgwick {
+    q: UnitQuaternion<f32>,
+    beta: f32,
+}
+
+impl Default for Madgwick {
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+impl Madgwick {
+    /// Filter with gain beta, e.g. 0.1. Higher values trust the accelerometer (and magnetometer) more
+    pub fn new(beta: f32) -> Self {
+        Madgwick {
+            q: UnitQuaternion::identity(),
+            beta,
+        }
+    }
+
+    /// Set gain beta
+    pub fn set_beta(&mut self, beta: f32) {
+        self.beta = beta;
+    }
+
+    /// Get gain beta
+    pub fn beta(&self) -> f32 {
+        self.beta
+    }
+
+    /// Overwrite the current estimate
+    pub fn set_quaternion(&mut self, q: UnitQuaternion<f32>) {
+        self.q = q;
+    }
+
+    /// 6-axis update: acc in g, gyro in rad/s, dt in s
+    pub fn update(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>, dt: f32) -> UnitQuaternion<f32> {
+        let q = *self.q.quaternion();
+        let mut q_dot = integrate(&q, gyro, 1.);
+
+        if let Some(a) = normalized(acc) {
+            let (w, x, y, z) = (q.w, q.i, q.j, q.k);
+            // objective function and jacobian for gravity [0 0 1], in (w x y z) order
+            let f = Vector3::new(
+                2. * (x * z - w * y) - a.x,
+                2. * (w * x + y * z) - a.y,
+                1. - 2. * (x * x + y * y) - a.z,
+            );
+            let j = Matrix3x4::new(
+                -2. * y, 2. * z, -2. * w, 2. * x,
+                2. * x, 2. * w, 2. * z, 2. * y,
+                0., -4. * x, -4. * y, 0.,
+            );
+            self.descend(&mut q_dot, j.transpose() * f);
+        }
+
+        self.step(q, q_dot, dt)
+    }
+
+    /// 9-axis update: acc in g, gyro in rad/s, mag in any unit, dt in s.
+    /// Falls back to `update` if the magnetometer reading is zero
+    pub fn update_marg(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>, mag: &Vector3<f32>, dt: f32) -> UnitQuaternion<f32> {
+        let (a, m) = match (normalized(acc), normalized(mag)) {
+            (Some(a), Some(m)) => (a, m),
+            _ => return self.update(acc, gyro, dt),
+        };
+
+        let q = *self.q.quaternion();
+        let mut q_dot = integrate(&q, gyro, 1.);
+        let (w, x, y, z) = (q.w, q.i, q.j, q.k);
+
+        // reference direction of earth's magnetic field, only horizontal and vertical component
+        let h = self.q.transform_vector(&m);
+        let bx = libm::sqrtf(h.x * h.x + h.y * h.y);
+        let bz = h.z;
+
+        let f_g = Vector3::new(
+            2. * (x * z - w * y) - a.x,
+            2. * (w * x + y * z) - a.y,
+            1. - 2. * (x * x + y * y) - a.z,
+        );
+        let j_g = Matrix3x4::new(
+            -2. * y, 2. * z, -2. * w, 2. * x,
+            2. * x, 2. * w, 2. * z, 2. * y,
+            0., -4. * x, -4. * y, 0.,
+        );
+        let f_m = Vector3::new(
+            bx * (1. - 2. * (y * y + z * z)) + 2. * bz * (x * z - w * y) - m.x,
+            2. * bx * (x * y - w * z) + 2. * bz * (w * x + y * z) - m.y,
+            2. * bx * (x * z + w * y) + bz * (1. - 2. * (x * x + y * y)) - m.z,
+        );
+        let j_m = Matrix3x4::new(
+            -2. * bz * y, 2. * bz * z, -4. * bx * y - 2. * bz * w, -4. * bx * z + 2. * bz * x,
+            -2. * bx * z + 2. * bz * x, 2. * bx * y + 2. * bz * w, 2. * bx * x + 2. * bz * z, -2. * bx * w + 2. * bz * y,
+            2. * bx * y, 2. * bx * z - 4. * bz * x, 2. * bx * w - 4. * bz * y, 2. * bx * x,
+        );
+
+        self.descend(&mut q_dot, j_g.transpose() * f_g + j_m.transpose() * f_m);
+        self.step(q, q_dot, dt)
+    }
+
+    /// Read acc and gyro from the sensor and feed them to the filter, see `update`
+    pub fn update_from<I2C, E>(&mut self, mpu: &mut Mpu6050<I2C>, dt: f32) -> Result<UnitQuaternion<f32>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error=E>,
+    {
+        let acc = mpu.get_acc()?;
+        let gyro = mpu.get_gyro()?;
+        Ok(self.update(&acc, &gyro, dt))
+    }
+
+    /// Subtracts the normalized gradient (w x y z order), scaled by beta, from q_dot
+    fn descend(&self, q_dot: &mut Quaternion<f32>, gradient: Vector4<f32>) {
+        if let Some(s) = gradient.try_normalize(0.) {
+            *q_dot -= Quaternion::new(s[0], s[1], s[2], s[3]) * self.beta;
+        }
+    }
+
+    fn step(&mut self, q: Quaternion<f32>, q_dot: Quaternion<f32>, dt: f32) -> UnitQuaternion<f32> {
+        self.q = UnitQuaternion::from_quaternion(q + q_dot * dt);
+        self.q
+    }
+}
+
+impl Orientation for Madgwick {
+    fn quaternion(&self) -> UnitQuaternion<f32> {
+        self.q
+    }
+}
+
+/// Mahony explicit complementary filter with PI correction
+#[derive(Copy, Clone, Debug)]
+pub struct Mahony {
+    q: UnitQuaternion<f32>,
+    kp: f32,
+    ki: f32,
+    integral: Vector3<f32>,
+}
+
+impl Default for Mahony {
+    fn default() -> Self {
+        Self::new(0.5, 0.)
+    }
+}
+
+impl Mahony {
+    /// Filter with proportional gain kp, e.g. 0.5, and integral gain ki, e.g. 0 (no gyro bias correction)
+    pub fn new(kp: f32, ki: f32) -> Self {
+        Mahony {
+            q: UnitQuaternion::identity(),
+            kp,
+            ki,
+            integral: Vector3::zeros(),
+        }
+    }
+
+    /// Set proportional gain kp and integral gain ki
+    pub fn set_gains(&mut self, kp: f32, ki: f32) {
+        self.kp = kp;
+        self.ki = ki;
+    }
+
+    /// Get (kp, ki)
+    pub fn gains(&self) -> (f32, f32) {
+        (self.kp, self.ki)
+    }
+
+    /// Integrated error term, an estimate of the (negative) gyro bias in rad/s
+    pub fn integral(&self) -> Vector3<f32> {
+        self.integral
+    }
+
+    /// Overwrite the current estimate, clears the integral term
+    pub fn set_quaternion(&mut self, q: UnitQuaternion<f32>) {
+        self.q = q;
+        self.integral = Vector3::zeros();
+    }
+
+    /// 6-axis update: acc in g, gyro in rad/s, dt in s
+    pub fn update(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>, dt: f32) -> UnitQuaternion<f32> {
+        let error = match normalized(acc) {
+            Some(a) => a.cross(&self.gravity()),
+            None => Vector3::zeros(),
+        };
+        self.correct(gyro, error, dt)
+    }
+
+    /// 9-axis update: acc in g, gyro in rad/s, mag in any unit, dt in s.
+    /// Falls back to `update` if the magnetometer reading is zero
+    pub fn update_marg(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>, mag: &Vector3<f32>, dt: f32) -> UnitQuaternion<f32> {
+        let (a, m) = match (normalized(acc), normalized(mag)) {
+            (Some(a), Some(m)) => (a, m),
+            _ => return self.update(acc, gyro, dt),
+        };
+
+        // reference direction of earth's magnetic field, rotated back into the sensor frame
+        let h = self.q.transform_vector(&m);
+        let b = Vector3::new(libm::sqrtf(h.x * h.x + h.y * h.y), 0., h.z);
+        let w = self.q.inverse_transform_vector(&b);
+
+        let error = a.cross(&self.gravity()) + m.cross(&w);
+        self.correct(gyro, error, dt)
+    }
+
+    /// Read acc and gyro from the sensor and feed them to the filter, see `update`
+    pub fn update_from<I2C, E>(&mut self, mpu: &mut Mpu6050<I2C>, dt: f32) -> Result<UnitQuaternion<f32>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error=E>,
+    {
+        let acc = mpu.get_acc()?;
+        let gyro = mpu.get_gyro()?;
+        Ok(self.update(&acc, &gyro, dt))
+    }
+
+    fn correct(&mut self, gyro: &Vector3<f32>, error: Vector3<f32>, dt: f32) -> UnitQuaternion<f32> {
+        if self.ki > 0. {
+            self.integral += error * self.ki * dt;
+        } else {
+            self.integral = Vector3::zeros();
+        }
+
+        let rate = gyro + error * self.kp + self.integral;
+        let q = *self.q.quaternion();
+        self.q = UnitQuaternion::from_quaternion(q + integrate(&q, &rate, dt));
+        self.q
+    }
+}
+
+impl Orientation for Mahony {
+    fn quaternion(&self) -> UnitQuaternion<f32> {
+        self.q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tilted() -> Vector3<f32> {
+        // roll by 0.4 rad: gravity in sensor frame
+        Vector3::new(0., libm::sinf(0.4), libm::cosf(0.4))
+    }
+
+    #[test]
+    fn madgwick_converges_test() {
+        let mut filter = Madgwick::new(0.5);
+        for _ in 0..2000 {
+            filter.update(&tilted(), &Vector3::zeros(), 0.01);
+        }
+        let (roll, pitch, _) = filter.euler_angles();
+        assert!((roll - 0.4).abs() < 1e-2);
+        assert!(pitch.abs() < 1e-2);
+        assert!((filter.gravity() - tilted()).amax() < 1e-2);
+    }
+
+    #[test]
+    fn mahony_converges_test() {
+        let mut filter = Mahony::new(2., 0.);
+        for _ in 0..2000 {
+            filter.update(&tilted(), &Vector3::zeros(), 0.01);
+        }
+        let (roll, pitch, _) = filter.euler_angles();
+        assert!((roll - 0.4).abs() < 1e-2);
+        assert!(pitch.abs() < 1e-2);
+    }
+
+    #[test]
+    fn marg_heading_test() {
+        // level board rotated by yaw 0.5 rad, north along world x
+        let acc = Vector3::z();
+        let mag = Vector3::new(libm::cosf(0.5), -libm::sinf(0.5), -0.5);
+        let mut madgwick = Madgwick::new(0.5);
+        let mut mahony = Mahony::new(2., 0.);
+        for _ in 0..3000 {
+            madgwick.update_marg(&acc, &Vector3::zeros(), &mag, 0.01);
+            mahony.update_marg(&acc, &Vector3::zeros(), &mag, 0.01);
+        }
+        assert!((madgwick.euler_angles().2 - 0.5).abs() < 2e-2);
+        assert!((mahony.euler_angles().2 - 0.5).abs() < 2e-2);
+    }
+}
@@ -0,0 +1,382 @@
+//! AK8963 magnetometer of the MPU9250 and MPU9255, behind the MPU on its aux I2C bus.
+//!
+//! Two ways to reach it:
+//! * `MagAccess::Bypass`: the aux bus is connected to the host bus, the AK8963 answers at
+//!   `AK8963_ADDR` next to the MPU
+//! * `MagAccess::AuxMaster`: the MPU reads the AK8963 on its own and provides the data in
+//!   EXT_SENS_DATA, for hosts that can't see the aux bus
+//!
+//! Readings are in µT, corrected with the factory sensitivity adjustment (ASA) and rotated into
+//! the accel/gyro frame, so `get_acc`, `get_gyro` and `get_mag` feed 9-DoF filters directly.
+//!
+//! ```no_run
+//! use mpu6050::{Mpu6050, ak8963::*};
+//! use linux_embedded_hal::{I2cdev, Delay};
+//!
+//! let mut mpu = Mpu6050::new(I2cdev::new("/dev/i2c-1").unwrap());
+//! mpu.init(&mut Delay).unwrap();
+//! mpu.init_magnetometer(&mut Delay, MagAccess::Bypass, MagMode::Continuous100Hz, MagResolution::Bits16).unwrap();
+//! let mag = mpu.get_mag().unwrap();
+//! ```
+
+use crate::device::*;
+use crate::{Mpu6050, Mpu6050Error};
+use core::fmt;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+use nalgebra::Vector3;
+
+/// Slave address of the AK8963
+pub const AK8963_ADDR: u8 = 0x0c;
+/// Register 0: Device ID
+pub const WIA: u8 = 0x00;
+/// Device ID of the AK8963
+pub const AK8963_ID: u8 = 0x48;
+/// Register 3: First measurement register, little endian X, Y, Z
+pub const HXL: u8 = 0x03;
+/// Register 16 - 18: Sensitivity adjustment X, Y, Z, readable in fuse ROM mode
+pub const ASAX: u8 = 0x10;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// AK8963 Register 2: Status 1
+pub struct ST1;
+
+impl ST1 {
+    /// Base Address
+    pub const ADDR: u8 = 0x02;
+    /// Data ready
+    pub const DRDY: u8 = 0;
+    /// Data overrun, a measurement was skipped
+    pub const DOR: u8 = 1;
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// AK8963 Register 9: Status 2, must be read after the measurement
+pub struct ST2;
+
+impl ST2 {
+    /// Base Address
+    pub const ADDR: u8 = 0x09;
+    /// Magnetic sensor overflow
+    pub const HOFL: u8 = 3;
+    /// Output bit setting mirror
+    pub const BITM: u8 = 4;
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// AK8963 Register 10: Control 1
+pub struct CNTL1;
+
+impl CNTL1 {
+    /// Base Address
+    pub const ADDR: u8 = 0x0a;
+    /// 16 bit output
+    pub const BIT: u8 = 4;
+    /// Operation mode
+    pub const MODE: BitBlock = BitBlock { bit: 3, length: 4 };
+    /// Power down mode
+    pub const POWER_DOWN: u8 = 0x00;
+    /// Fuse ROM access mode
+    pub const FUSE_ROM: u8 = 0x0f;
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// AK8963 Register 11: Control 2
+pub struct CNTL2;
+
+impl CNTL2 {
+    /// Base Address
+    pub const ADDR: u8 = 0x0b;
+    /// Soft reset
+    pub const SRST: u8 = 0;
+}
+
+/// Registers ST1 to ST2: status, measurement, status
+const MEASUREMENT_LEN: usize = 8;
+
+/// How the AK8963 is reached
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MagAccess {
+    /// Aux bus bypassed to the host bus
+    Bypass,
+    /// Read by the MPU aux I2C master into EXT_SENS_DATA
+    AuxMaster,
+}
+
+/// Continuous measurement modes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MagMode {
+    /// 8 Hz
+    Continuous8Hz = 0x02,
+    /// 100 Hz
+    Continuous100Hz = 0x06,
+}
+
+/// Output resolution
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MagResolution {
+    /// 14 bit, 0.6 µT/LSB
+    Bits14 = 0,
+    /// 16 bit, 0.15 µT/LSB
+    Bits16 = 1,
+}
+
+impl MagResolution {
+    /// Scale in µT/LSB, full scale is 4912 µT
+    pub fn scale(&self) -> f32 {
+        match self {
+            MagResolution::Bits14 => 4912. / 8190.,
+            MagResolution::Bits16 => 4912. / 32760.,
+        }
+    }
+}
+
+/// Magnetometer errors
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MagError {
+    /// `init_magnetometer` hasn't run
+    NotInitialized,
+    /// WIA isn't `AK8963_ID`
+    InvalidId(u8),
+}
+
+impl fmt::Display for MagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MagError::NotInitialized => write!(f, "not initialized"),
+            MagError::InvalidId(id) => write!(f, "invalid AK8963 id 0x{:02x}", id),
+        }
+    }
+}
+
+/// State of an initialised AK8963
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Magnetometer {
+    access: MagAccess,
+    resolution: MagResolution,
+    adjustment: Vector3<f32>,
+    overrun: bool,
+}
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Set up the aux bus, check the AK8963 ID, read the sensitivity adjustment and start
+    /// continuous measurements. Needs a part with magnetometer, see `ChipVariant::has_magnetometer`
+    pub fn init_magnetometer<D: DelayNs>(&mut self, delay: &mut D, access: MagAccess, mode: MagMode, resolution: MagResolution) -> Result<(), Mpu6050Error<E>> {
+        if !self.variant.has_magnetometer() {
+            return Err(Mpu6050Error::Unsupported(self.variant));
+        }
+        self.magnetometer = None;
+
+        match access {
+            MagAccess::Bypass => {
+                self.write_bit(USER_CTRL::ADDR, USER_CTRL::I2C_MST_EN, false)?;
+                self.set_bypass_enabled(true)?;
+            }
+            MagAccess::AuxMaster => {
+                self.set_bypass_enabled(false)?;
+                self.write_bit(USER_CTRL::ADDR, USER_CTRL::I2C_MST_EN, true)?;
+                self.write_byte(I2C_MST_CTRL, I2C_MST_CLK_400)?;
+            }
+        }
+
+        let mut id = [0];
+        self.mag_read(delay, access, WIA, &mut id)?;
+        if id[0] != AK8963_ID {
+            return Err(Mpu6050Error::Magnetometer(MagError::InvalidId(id[0])));
+        }
+
+        self.mag_write(delay, access, CNTL1::ADDR, CNTL1::POWER_DOWN)?;
+        self.mag_write(delay, access, CNTL1::ADDR, CNTL1::FUSE_ROM)?;
+        let mut asa = [0; 3];
+        self.mag_read(delay, access, ASAX, &mut asa)?;
+        self.mag_write(delay, access, CNTL1::ADDR, CNTL1::POWER_DOWN)?;
+        self.mag_write(delay, access, CNTL1::ADDR, ((resolution as u8) << CNTL1::BIT) | mode as u8)?;
+
+        if access == MagAccess::AuxMaster {
+            // continuous read of the status and measurement registers into EXT_SENS_DATA
+            self.write_byte(I2C_SLV0::ADDR, (1 << I2C_SLV0::RNW) | AK8963_ADDR)?;
+            self.write_byte(I2C_SLV0::REG, ST1::ADDR)?;
+            self.write_byte(I2C_SLV0::CTRL, (1 << I2C_SLV0::EN) | MEASUREMENT_LEN as u8)?;
+        }
+
+        self.magnetometer = Some(Magnetometer {
+            access,
+            resolution,
+            adjustment: Vector3::from_fn(|i, _| (asa[i] as f32 - 128.) / 256. + 1.),
+            overrun: false,
+        });
+        Ok(())
+    }
+
+    /// Magnetic field in µT in the accel/gyro frame, sensitivity adjusted.
+    /// Fails with `Saturated` if the field exceeded the 4912 µT measurement range, and with
+    /// `DataNotReady` if no measurement finished since the last read
+    pub fn get_mag(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mag = self.magnetometer.ok_or(Mpu6050Error::Magnetometer(MagError::NotInitialized))?;
+        let raw = self.get_mag_raw()?;
+        let field = raw.map(|v| v as f32).component_mul(&mag.adjustment) * mag.resolution.scale();
+
+        // AK8963 x is MPU y, AK8963 z points down
+        Ok(Vector3::new(field.y, field.x, -field.z))
+    }
+
+    /// Raw AK8963 measurement in its own axes, without adjustment.
+    /// Fails with `DataNotReady` if no measurement finished since the last read
+    pub fn get_mag_raw(&mut self) -> Result<Vector3<i16>, Mpu6050Error<E>> {
+        let mag = self.magnetometer.ok_or(Mpu6050Error::Magnetometer(MagError::NotInitialized))?;
+        let mut buf = [0; MEASUREMENT_LEN];
+        match mag.access {
+            // reading through ST2 releases the data lock
+            MagAccess::Bypass => self.i2c.write_read(AK8963_ADDR, &[ST1::ADDR], &mut buf).map_err(Mpu6050Error::I2c)?,
+            MagAccess::AuxMaster => self.read_bytes(EXT_SENS_DATA_00, &mut buf)?,
+        }
+
+        if buf[0] & (1 << ST1::DRDY) == 0 {
+            return Err(Mpu6050Error::DataNotReady { reg: ST1::ADDR });
+        }
+        if let Some(mag) = self.magnetometer.as_mut() {
+            mag.overrun = buf[0] & (1 << ST1::DOR) != 0;
+        }
+        if buf[7] & (1 << ST2::HOFL) != 0 {
+            return Err(Mpu6050Error::Saturated { reg: ST2::ADDR });
+        }
+        Ok(Vector3::new(
+            i16::from_le_bytes([buf[1], buf[2]]),
+            i16::from_le_bytes([buf[3], buf[4]]),
+            i16::from_le_bytes([buf[5], buf[6]]),
+        ))
+    }
+
+    /// Whether measurements were skipped before the last successful read (ST1 DOR): the
+    /// magnetometer is polled slower than its `MagMode`
+    pub fn get_mag_overrun(&self) -> bool {
+        self.magnetometer.is_some_and(|mag| mag.overrun)
+    }
+
+    /// Factory sensitivity adjustment per AK8963 axis, `None` before `init_magnetometer`
+    pub fn get_mag_adjustment(&self) -> Option<Vector3<f32>> {
+        self.magnetometer.map(|mag| mag.adjustment)
+    }
+
+    /// Write an AK8963 register and give it time to act. The aux master writes with the next sample
+    fn mag_write<D: DelayNs>(&mut self, delay: &mut D, access: MagAccess, reg: u8, byte: u8) -> Result<(), Mpu6050Error<E>> {
+        match access {
+            MagAccess::Bypass => self.i2c.write(AK8963_ADDR, &[reg, byte]).map_err(Mpu6050Error::I2c)?,
+            MagAccess::AuxMaster => {
+                self.write_byte(I2C_SLV0::ADDR, AK8963_ADDR)?;
+                self.write_byte(I2C_SLV0::REG, reg)?;
+                self.write_byte(I2C_SLV0::DO, byte)?;
+                self.write_byte(I2C_SLV0::CTRL, (1 << I2C_SLV0::EN) | 1)?;
+                delay.delay_ms(10u32);
+                return self.check_aux_ack(reg);
+            }
+        }
+        delay.delay_ms(10u32);
+        Ok(())
+    }
+
+    /// Read AK8963 registers, at most 24 via the aux master
+    fn mag_read<D: DelayNs>(&mut self, delay: &mut D, access: MagAccess, reg: u8, buf: &mut [u8]) -> Result<(), Mpu6050Error<E>> {
+        match access {
+            MagAccess::Bypass => self.i2c.write_read(AK8963_ADDR, &[reg], buf).map_err(Mpu6050Error::I2c),
+            MagAccess::AuxMaster => {
+                self.write_byte(I2C_SLV0::ADDR, (1 << I2C_SLV0::RNW) | AK8963_ADDR)?;
+                self.write_byte(I2C_SLV0::REG, reg)?;
+                self.write_byte(I2C_SLV0::CTRL, (1 << I2C_SLV0::EN) | buf.len() as u8)?;
+                delay.delay_ms(10u32);
+                self.check_aux_ack(reg)?;
+                self.read_bytes(EXT_SENS_DATA_00, buf)
+            }
+        }
+    }
+
+    /// Fails with `AuxNack` if the last slave 0 transfer wasn't acknowledged
+    fn check_aux_ack(&mut self, reg: u8) -> Result<(), Mpu6050Error<E>> {
+        if self.read_bit(I2C_MST_STATUS::ADDR, I2C_MST_STATUS::I2C_SLV0_NACK)? != 0 {
+            return Err(Mpu6050Error::AuxNack { address: AK8963_ADDR, reg });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMpu6050, NoDelay};
+
+    #[test]
+    fn magnetometer_test() {
+        for access in [MagAccess::Bypass, MagAccess::AuxMaster] {
+            let mut mock = MockMpu6050::new();
+            mock.set_who_am_i(0x71);
+            mock.set_mag_asa([128, 192, 64]);
+            let mut mpu = Mpu6050::new(mock);
+            mpu.init(&mut NoDelay).unwrap();
+            assert!(matches!(mpu.get_mag(), Err(Mpu6050Error::Magnetometer(MagError::NotInitialized))));
+
+            mpu.init_magnetometer(&mut NoDelay, access, MagMode::Continuous100Hz, MagResolution::Bits16).unwrap();
+            assert_eq!(mpu.get_mag_adjustment(), Some(Vector3::new(1., 1.25, 0.75)));
+            assert_eq!(mpu.i2c.mag_register(CNTL1::ADDR), 0x16);
+
+            mpu.i2c.set_mag_raw([1000, 2000, -4000]);
+            assert_eq!(mpu.get_mag_raw().unwrap(), Vector3::new(1000, 2000, -4000));
+            assert!(!mpu.get_mag_overrun());
+            mpu.i2c.set_mag_raw([1000, 2000, -4000]);
+            let scale = MagResolution::Bits16.scale();
+            assert_eq!(mpu.get_mag().unwrap(), Vector3::new(2500. * scale, 1000. * scale, 3000. * scale));
+
+            mpu.i2c.set_mag_overflow(true);
+            mpu.i2c.set_mag_raw([1000, 2000, -4000]);
+            assert!(matches!(mpu.get_mag(), Err(Mpu6050Error::Saturated { reg: ST2::ADDR })));
+        }
+
+        // no magnetometer in the MPU6050
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.init(&mut NoDelay).unwrap();
+        assert!(matches!(mpu.init_magnetometer(&mut NoDelay, MagAccess::Bypass, MagMode::Continuous8Hz, MagResolution::Bits14),
+            Err(Mpu6050Error::Unsupported(ChipVariant::Mpu6050))));
+
+        // nobody answers on the aux bus of an MPU6500
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x70);
+        let mut mpu = Mpu6050::new(mock);
+        mpu.init(&mut NoDelay).unwrap();
+        mpu.write_bit(USER_CTRL::ADDR, USER_CTRL::I2C_MST_EN, true).unwrap();
+        assert!(matches!(mpu.mag_read(&mut NoDelay, MagAccess::AuxMaster, WIA, &mut [0]),
+            Err(Mpu6050Error::AuxNack { address: AK8963_ADDR, reg: WIA })));
+        assert!(mpu.mag_write(&mut NoDelay, MagAccess::AuxMaster, CNTL2::ADDR, 1).is_err());
+    }
+
+    #[test]
+    fn mag_status_test() {
+        for access in [MagAccess::Bypass, MagAccess::AuxMaster] {
+            let mut mock = MockMpu6050::new();
+            mock.set_who_am_i(0x71);
+            let mut mpu = Mpu6050::new(mock);
+            mpu.init(&mut NoDelay).unwrap();
+            mpu.init_magnetometer(&mut NoDelay, access, MagMode::Continuous100Hz, MagResolution::Bits16).unwrap();
+
+            // ST1 DRDY clear: nothing new since the last read
+            assert!(matches!(mpu.get_mag_raw(), Err(Mpu6050Error::DataNotReady { reg: ST1::ADDR })));
+            mpu.i2c.set_mag_raw([10, 20, 30]);
+            mpu.get_mag_raw().unwrap();
+            assert!(matches!(mpu.get_mag(), Err(Mpu6050Error::DataNotReady { reg: ST1::ADDR })));
+
+            // second sample before the first was read: DOR
+            mpu.i2c.set_mag_raw([10, 20, 30]);
+            mpu.i2c.set_mag_raw([40, 50, 60]);
+            assert_eq!(mpu.get_mag_raw().unwrap(), Vector3::new(40, 50, 60));
+            assert!(mpu.get_mag_overrun());
+            mpu.i2c.set_mag_raw([10, 20, 30]);
+            mpu.get_mag_raw().unwrap();
+            assert!(!mpu.get_mag_overrun());
+        }
+    }
+}
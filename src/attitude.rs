@@ -0,0 +1,158 @@
+//! Attitude estimation from accelerometer and gyro readings.
+//!
+//! Accelerometer angles are noisy but don't drift, integrated gyro rates are smooth but do.
+//! The complementary filter blends both:
+//!
+//! angle = alpha * (angle + gyro * dt) + (1 - alpha) * acc_angle
+//!
+//! Sources:
+//! * https://www.nxp.com/docs/en/application-note/AN3461.pdf
+//! * http://www.pieter-jan.com/node/11
+
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::i2c::I2c;
+use libm::{atan2f, sqrtf};
+use nalgebra::{Vector2, Vector3};
+
+/// Roll and pitch from a single accelerometer reading
+/// https://www.nxp.com/docs/en/application-note/AN3461.pdf equation 28, 29
+pub fn acc_angles(acc: &Vector3<f32>) -> Vector2<f32> {
+    Vector2::<f32>::new(
+        atan2f(acc.y, sqrtf(acc.x * acc.x + acc.z * acc.z)),
+        atan2f(-acc.x, sqrtf(acc.y * acc.y + acc.z * acc.z))
+    )
+}
+
+/// How much the filter trusts the integrated gyro over the accelerometer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Blend {
+    /// Fixed weight of the gyro path, 0..1. Only valid for a constant dt
+    Alpha(f32),
+    /// Time constant in s. Alpha is recalculated for each dt: tau / (tau + dt)
+    TimeConstant(f32),
+}
+
+impl Blend {
+    fn alpha(&self, dt: f32) -> f32 {
+        match *self {
+            Blend::Alpha(alpha) => alpha,
+            Blend::TimeConstant(tau) => tau / (tau + dt),
+        }
+    }
+}
+
+/// Complementary filter for roll and pitch, in rad
+#[derive(Copy, Clone, Debug)]
+pub struct ComplementaryFilter {
+    blend: Blend,
+    angles: Vector2<f32>,
+    initialized: bool,
+}
+
+impl ComplementaryFilter {
+    /// Filter with fixed gyro weight alpha, e.g. 0.98
+    pub fn new(alpha: f32) -> Self {
+        Self::with_blend(Blend::Alpha(alpha))
+    }
+
+    /// Filter with time constant tau in s, e.g. 0.5
+    pub fn new_with_time_constant(tau: f32) -> Self {
+        Self::with_blend(Blend::TimeConstant(tau))
+    }
+
+    /// Filter with any blend setting
+    pub fn with_blend(blend: Blend) -> Self {
+        ComplementaryFilter {
+            blend,
+            angles: Vector2::zeros(),
+            initialized: false,
+        }
+    }
+
+    /// Change blend setting, keeps the current estimate
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    /// Current blend setting
+    pub fn blend(&self) -> Blend {
+        self.blend
+    }
+
+    /// Forget the current estimate. The next update starts from the accelerometer angles
+    pub fn reset(&mut self) {
+        self.angles = Vector2::zeros();
+        self.initialized = false;
+    }
+
+    /// Feed one sample: acc in g, gyro in rad/s, dt in s. Returns (roll, pitch) in rad
+    pub fn update(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>, dt: f32) -> Vector2<f32> {
+        let acc_angles = acc_angles(acc);
+
+        if !self.initialized {
+            self.angles = acc_angles;
+            self.initialized = true;
+            return self.angles;
+        }
+
+        let alpha = self.blend.alpha(dt);
+        let gyro_angles = self.angles + Vector2::new(gyro.x, gyro.y) * dt;
+        self.angles = gyro_angles * alpha + acc_angles * (1. - alpha);
+        self.angles
+    }
+
+    /// Read acc and gyro from the sensor and feed them to the filter, see `update`
+    pub fn update_from<I2C, E>(&mut self, mpu: &mut Mpu6050<I2C>, dt: f32) -> Result<Vector2<f32>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error=E>,
+    {
+        let acc = mpu.get_acc()?;
+        let gyro = mpu.get_gyro()?;
+        Ok(self.update(&acc, &gyro, dt))
+    }
+
+    /// Roll and pitch estimate in rad
+    pub fn angles(&self) -> Vector2<f32> {
+        self.angles
+    }
+
+    /// Roll estimate in rad
+    pub fn roll(&self) -> f32 {
+        self.angles.x
+    }
+
+    /// Pitch estimate in rad
+    pub fn pitch(&self) -> f32 {
+        self.angles.y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complementary_filter_test() {
+        let mut filter = ComplementaryFilter::new_with_time_constant(0.1);
+        let level = Vector3::new(0., 0., 1.);
+        let still = Vector3::zeros();
+
+        // first sample initializes from acc
+        let angles = filter.update(&level, &still, 0.01);
+        assert!(angles.x.abs() < 1e-6 && angles.y.abs() < 1e-6);
+
+        // constant gyro rate without acc support should be damped, not integrated forever
+        for _ in 0..1000 {
+            filter.update(&level, &Vector3::new(0.1, 0., 0.), 0.01);
+        }
+        assert!(filter.roll() > 0. && filter.roll() < 0.02);
+
+        // tilted board converges to the acc angles
+        let tilted = Vector3::new(0., libm::sinf(0.3), libm::cosf(0.3));
+        for _ in 0..1000 {
+            filter.update(&tilted, &still, 0.01);
+        }
+        assert!((filter.roll() - 0.3).abs() < 1e-3);
+        assert!(filter.pitch().abs() < 1e-3);
+    }
+}
@@ -22,7 +22,7 @@ pub fn get_bits(mut byte: u8, bit_start: u8, length: u8) -> u8 {
     // therefore just "cut off" at 0 shift
     let mask_shift: u8 = if bit_start < length { 0 } else { bit_start - length + 1 };
     let mask: u8 = ((1 << length) - 1) << mask_shift;
-    byte &= mask as u8;
+    byte &= mask;
     byte >>= mask_shift;
     byte
 }
@@ -128,8 +128,8 @@ mod tests {
         assert_eq!(value, bits);
 
         // simulate accel_hpf
-        let bitstart = Bits::ACCEL_CONFIG_ACCEL_HPF_BIT;
-        let length = Bits::ACCEL_CONFIG_ACCEL_HPF_LENGTH;
+        let bitstart = ACCEL_CONFIG::ACCEL_HPF.bit;
+        let length = ACCEL_CONFIG::ACCEL_HPF.length;
         assert_eq!(get_bits(original_value, bitstart, length), 0b00000011);
 
         let mode: u8 = 7;
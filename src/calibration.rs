@@ -0,0 +1,845 @@
+//! Sensor calibration.
+//!
+//! #### Gyro bias
+//! Every gyro reads a small non zero rate at rest. Uncorrected, integrating it makes the heading
+//! drift by several degrees per minute. `GyroBiasEstimator` averages windows of samples taken
+//! while the sensor is still (low accelerometer and gyro variance) and rejects windows with movement.
+//! The first accepted window sets the bias, later windows refine it with a low pass.
+//!
+//! The driver subtracts the bias in `get_gyro`, see `Mpu6050::calibrate_gyro` and
+//! `Mpu6050::set_gyro_bias_tracking`.
+//!
+//! #### Accelerometer scale and misalignment
+//! Cheap boards show offsets and several percent of scale error, and the axes are not perfectly
+//! orthogonal. Modelling the raw reading as `raw = A * true + b`, with A holding scale and
+//! cross axis terms, six still readings with one axis pointing straight up or down solve A and b
+//! in closed form: b is the mean of opposite poses, column i of A is half their difference.
+//! `AccelCalibrator` collects these poses, the driver applies `A⁻¹ * (raw - b)` in `get_acc`.
+//!
+//! #### Temperature compensation
+//! Gyro (and to a lesser extent accelerometer) bias moves with die temperature, far more over
+//! -20..50 °C than the filters can follow. `TempSweep` records stationary readings against
+//! `get_temp` during a slow thermal sweep and fits a polynomial of up to third order per axis in
+//! (T - T0). Only the temperature dependent terms are corrected, the offset at the reference
+//! temperature T0 stays with the gyro bias and accelerometer calibration above.
+//!
+//! #### Persistence
+//! `Calibration` bundles all of the above. `Calibration::to_bytes` writes a compact, versioned
+//! blob with CRC for EEPROM/flash, with the `serde` feature it can also be stored as JSON/TOML etc.
+//!
+//! Blob layout, little endian:
+//!
+//! | Offset | Size | Content |
+//! |:-------|:-----|:--------|
+//! | 0   | 2   | magic `b"MC"` |
+//! | 2   | 1   | format version |
+//! | 3   | 1   | flags, bit 0: temperature compensation present |
+//! | 4   | 160 | 40 x f32: gyro bias (3), accel matrix (9, column major), accel bias (3), T0 (1), gyro temp coefficients (12, column major), accel temp coefficients (12, column major) |
+//! | 164 | 2   | CRC-16/CCITT-FALSE over bytes 0..164 |
+
+use nalgebra::{Matrix3, Matrix3x4, Matrix4, Matrix4x3, Vector3, Vector4};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Running mean and variance of a vector signal (Welford)
+#[derive(Copy, Clone, Debug)]
+struct Stats {
+    n: u16,
+    mean: Vector3<f32>,
+    m2: Vector3<f32>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Stats { n: 0, mean: Vector3::zeros(), m2: Vector3::zeros() }
+    }
+
+    fn add(&mut self, x: &Vector3<f32>) {
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f32;
+        self.m2 += delta.component_mul(&(x - self.mean));
+    }
+
+    /// Largest per axis sample variance
+    fn max_variance(&self) -> f32 {
+        if self.n < 2 {
+            return 0.;
+        }
+        self.m2.max() / (self.n - 1) as f32
+    }
+}
+
+/// Result of feeding a sample to `GyroBiasEstimator`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BiasUpdate {
+    /// Window not complete yet
+    Collecting,
+    /// Window complete and still, bias was updated
+    Updated,
+    /// Window complete, but the sensor moved. Samples were discarded
+    Rejected,
+}
+
+/// Estimates gyro bias from windows of samples taken at rest
+#[derive(Copy, Clone, Debug)]
+pub struct GyroBiasEstimator {
+    window: u16,
+    acc_variance: f32,
+    gyro_variance: f32,
+    rate_limit: f32,
+    gravity_tolerance: f32,
+    refine_rate: f32,
+    acc: Stats,
+    gyro: Stats,
+    bias: Vector3<f32>,
+    calibrated: bool,
+}
+
+impl Default for GyroBiasEstimator {
+    fn default() -> Self {
+        Self::new(200)
+    }
+}
+
+impl GyroBiasEstimator {
+    /// Default accelerometer variance limit for stillness, g²
+    pub const ACC_VARIANCE: f32 = 2e-4;
+    /// Default gyro variance limit for stillness, (rad/s)²
+    pub const GYRO_VARIANCE: f32 = 1e-4;
+    /// Default limit of the window mean rate minus the current bias, rad/s, once calibrated
+    pub const RATE_LIMIT: f32 = 0.05;
+    /// Default limit of the deviation of the mean acceleration magnitude from 1 g
+    pub const GRAVITY_TOLERANCE: f32 = 0.1;
+    /// Default weight of a new window when refining an existing bias
+    pub const REFINE_RATE: f32 = 0.1;
+
+    /// Estimator averaging `window` samples with default stillness limits
+    pub fn new(window: u16) -> Self {
+        GyroBiasEstimator {
+            window: window.max(2),
+            acc_variance: Self::ACC_VARIANCE,
+            gyro_variance: Self::GYRO_VARIANCE,
+            rate_limit: Self::RATE_LIMIT,
+            gravity_tolerance: Self::GRAVITY_TOLERANCE,
+            refine_rate: Self::REFINE_RATE,
+            acc: Stats::new(),
+            gyro: Stats::new(),
+            bias: Vector3::zeros(),
+            calibrated: false,
+        }
+    }
+
+    /// Set stillness limits: max per axis variance of acc (g²) and gyro ((rad/s)²) within a window
+    pub fn set_stillness_limits(&mut self, acc_variance: f32, gyro_variance: f32) {
+        self.acc_variance = acc_variance;
+        self.gyro_variance = gyro_variance;
+    }
+
+    /// Set limits that tell a steady rotation, which has a low variance, from rest: max per axis
+    /// difference of the mean rate to the current bias (rad/s, checked once calibrated) and max
+    /// deviation of the mean acceleration magnitude from 1 g
+    pub fn set_motion_limits(&mut self, rate_limit: f32, gravity_tolerance: f32) {
+        self.rate_limit = rate_limit;
+        self.gravity_tolerance = gravity_tolerance;
+    }
+
+    /// Set weight (0..1) of each new still window once a bias exists
+    pub fn set_refine_rate(&mut self, rate: f32) {
+        self.refine_rate = rate;
+    }
+
+    /// Samples per window
+    pub fn window(&self) -> u16 {
+        self.window
+    }
+
+    /// Current bias estimate, rad/s
+    pub fn bias(&self) -> Vector3<f32> {
+        self.bias
+    }
+
+    /// Start from a known bias, e.g. a stored calibration. Later windows refine it
+    pub fn set_bias(&mut self, bias: Vector3<f32>) {
+        self.bias = bias;
+        self.calibrated = true;
+    }
+
+    /// Whether at least one still window was accepted (or a bias was set)
+    pub fn is_calibrated(&self) -> bool {
+        self.calibrated
+    }
+
+    /// Feed one sample: acc in g, uncorrected gyro in rad/s
+    pub fn add_sample(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>) -> BiasUpdate {
+        self.acc.add(acc);
+        self.gyro.add(gyro);
+
+        if self.acc.n < self.window {
+            return BiasUpdate::Collecting;
+        }
+
+        let mean = self.gyro.mean;
+        let still = self.acc.max_variance() < self.acc_variance
+            && self.gyro.max_variance() < self.gyro_variance
+            && libm::fabsf(self.acc.mean.norm() - 1.) < self.gravity_tolerance
+            && (!self.calibrated || (mean - self.bias).amax() < self.rate_limit);
+        self.acc = Stats::new();
+        self.gyro = Stats::new();
+
+        if !still {
+            return BiasUpdate::Rejected;
+        }
+
+        if self.calibrated {
+            self.bias += (mean - self.bias) * self.refine_rate;
+        } else {
+            self.bias = mean;
+            self.calibrated = true;
+        }
+        BiasUpdate::Updated
+    }
+}
+
+/// Scale/misalignment matrix and bias applied to accelerometer readings:
+/// `calibrated = matrix * (raw - bias)`, all in g
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AccelCalibration {
+    /// Inverse of the scale and misalignment matrix
+    pub matrix: Matrix3<f32>,
+    /// Offset, g
+    pub bias: Vector3<f32>,
+}
+
+impl Default for AccelCalibration {
+    /// No correction
+    fn default() -> Self {
+        AccelCalibration {
+            matrix: Matrix3::identity(),
+            bias: Vector3::zeros(),
+        }
+    }
+}
+
+impl AccelCalibration {
+    /// Correct a raw reading in g
+    pub fn apply(&self, raw: &Vector3<f32>) -> Vector3<f32> {
+        self.matrix * (raw - self.bias)
+    }
+}
+
+/// Calibration pose: which sensor axis points up, against gravity
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Pose {
+    /// +x up
+    XUp = 0,
+    /// +x down
+    XDown,
+    /// +y up
+    YUp,
+    /// +y down
+    YDown,
+    /// +z up (board lying flat)
+    ZUp,
+    /// +z down (board upside down)
+    ZDown,
+}
+
+impl Pose {
+    /// All poses in the order `AccelCalibrator::next_pose` asks for them
+    pub const ALL: [Pose; 6] = [Pose::ZUp, Pose::ZDown, Pose::XUp, Pose::XDown, Pose::YUp, Pose::YDown];
+
+    /// Ideal reading in g
+    pub fn gravity(&self) -> Vector3<f32> {
+        match self {
+            Pose::XUp => Vector3::x(),
+            Pose::XDown => -Vector3::x(),
+            Pose::YUp => Vector3::y(),
+            Pose::YDown => -Vector3::y(),
+            Pose::ZUp => Vector3::z(),
+            Pose::ZDown => -Vector3::z(),
+        }
+    }
+
+    /// Pose of a reading, if one axis clearly dominates (> 0.8 g, others < 0.3 g)
+    pub fn detect(acc: &Vector3<f32>) -> Option<Pose> {
+        let axis = acc.iamax();
+        let others_small = (0..3).filter(|&i| i != axis).all(|i| acc[i].abs() < 0.3);
+        if acc[axis].abs() < 0.8 || !others_small {
+            return None;
+        }
+
+        let up = acc[axis] > 0.;
+        Some(match (axis, up) {
+            (0, true) => Pose::XUp,
+            (0, false) => Pose::XDown,
+            (1, true) => Pose::YUp,
+            (1, false) => Pose::YDown,
+            (_, true) => Pose::ZUp,
+            (_, false) => Pose::ZDown,
+        })
+    }
+}
+
+/// Result of feeding a sample to `AccelCalibrator`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PoseUpdate {
+    /// Window not complete yet
+    Collecting,
+    /// Window complete and still, pose stored
+    Accepted(Pose),
+    /// Pose had already been collected, window discarded
+    Duplicate(Pose),
+    /// Sensor moved during the window or no axis was aligned with gravity, window discarded
+    Rejected,
+}
+
+/// Collects the six still poses needed for `AccelCalibration`
+#[derive(Copy, Clone, Debug)]
+pub struct AccelCalibrator {
+    window: u16,
+    variance: f32,
+    stats: Stats,
+    poses: [Option<Vector3<f32>>; 6],
+}
+
+impl Default for AccelCalibrator {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl AccelCalibrator {
+    /// Default accelerometer variance limit for a stable pose, g²
+    pub const VARIANCE: f32 = 2e-4;
+
+    /// Calibrator averaging `window` uncorrected readings per pose
+    pub fn new(window: u16) -> Self {
+        AccelCalibrator {
+            window: window.max(2),
+            variance: Self::VARIANCE,
+            stats: Stats::new(),
+            poses: [None; 6],
+        }
+    }
+
+    /// Set max per axis variance (g²) for a pose to count as stable
+    pub fn set_variance_limit(&mut self, variance: f32) {
+        self.variance = variance;
+    }
+
+    /// Next pose still missing, None once all six are collected
+    pub fn next_pose(&self) -> Option<Pose> {
+        Pose::ALL.iter().copied().find(|pose| self.poses[*pose as usize].is_none())
+    }
+
+    /// Whether all six poses are collected
+    pub fn is_complete(&self) -> bool {
+        self.next_pose().is_none()
+    }
+
+    /// Drop a collected pose, e.g. to repeat it
+    pub fn clear_pose(&mut self, pose: Pose) {
+        self.poses[pose as usize] = None;
+    }
+
+    /// Feed one uncorrected reading in g. The pose is detected from the window mean,
+    /// so poses may be collected in any order
+    pub fn add_sample(&mut self, acc: &Vector3<f32>) -> PoseUpdate {
+        self.stats.add(acc);
+        if self.stats.n < self.window {
+            return PoseUpdate::Collecting;
+        }
+
+        let stats = self.stats;
+        self.stats = Stats::new();
+
+        if stats.max_variance() >= self.variance {
+            return PoseUpdate::Rejected;
+        }
+
+        match Pose::detect(&stats.mean) {
+            None => PoseUpdate::Rejected,
+            Some(pose) if self.poses[pose as usize].is_some() => PoseUpdate::Duplicate(pose),
+            Some(pose) => {
+                self.poses[pose as usize] = Some(stats.mean);
+                PoseUpdate::Accepted(pose)
+            }
+        }
+    }
+
+    /// Solve offset, scale and misalignment. None if poses are missing or the result is singular
+    pub fn solve(&self) -> Option<AccelCalibration> {
+        let mut means = [Vector3::zeros(); 6];
+        for (mean, pose) in means.iter_mut().zip(self.poses.iter()) {
+            *mean = (*pose)?;
+        }
+
+        let up = |i: usize| means[2 * i];
+        let down = |i: usize| means[2 * i + 1];
+
+        let bias = (0..3).map(|i| (up(i) + down(i)) / 2.).fold(Vector3::zeros(), |acc, b| acc + b) / 3.;
+        let a = Matrix3::from_columns(&[
+            (up(0) - down(0)) / 2.,
+            (up(1) - down(1)) / 2.,
+            (up(2) - down(2)) / 2.,
+        ]);
+
+        Some(AccelCalibration {
+            matrix: a.try_inverse()?,
+            bias,
+        })
+    }
+}
+
+/// Per axis polynomial of the bias drift over temperature
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TempModel {
+    /// Rows: x y z axis, columns: coefficients of (T - T0)^0..3
+    pub coefficients: Matrix3x4<f32>,
+}
+
+impl Default for TempModel {
+    /// No drift
+    fn default() -> Self {
+        TempModel { coefficients: Matrix3x4::zeros() }
+    }
+}
+
+impl TempModel {
+    /// Drift relative to the reference temperature, for a temperature offset dt = T - T0.
+    /// The constant term is not included
+    pub fn drift(&self, dt: f32) -> Vector3<f32> {
+        self.coefficients * Vector4::new(0., dt, dt * dt, dt * dt * dt)
+    }
+}
+
+/// Temperature dependent drift of gyro and accelerometer readings
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TempCompensation {
+    /// Reference temperature T0, °C
+    pub reference: f32,
+    /// Gyro drift, rad/s
+    pub gyro: TempModel,
+    /// Accelerometer drift, g
+    pub acc: TempModel,
+}
+
+impl Default for TempCompensation {
+    /// No compensation, reference at 25 °C
+    fn default() -> Self {
+        TempCompensation {
+            reference: 25.,
+            gyro: TempModel::default(),
+            acc: TempModel::default(),
+        }
+    }
+}
+
+impl TempCompensation {
+    /// Gyro drift at temperature temp (°C), rad/s
+    pub fn gyro_drift(&self, temp: f32) -> Vector3<f32> {
+        self.gyro.drift(temp - self.reference)
+    }
+
+    /// Accelerometer drift at temperature temp (°C), g
+    pub fn acc_drift(&self, temp: f32) -> Vector3<f32> {
+        self.acc.drift(temp - self.reference)
+    }
+}
+
+/// Records stationary readings over temperature and fits `TempCompensation` by least squares.
+/// Only the normal equations are accumulated, so sweeps of any length take constant memory.
+/// Keep the sensor still and in the same pose during the whole sweep
+#[derive(Copy, Clone, Debug)]
+pub struct TempSweep {
+    degree: usize,
+    reference: f32,
+    ata: Matrix4<f64>,
+    aty_gyro: Matrix4x3<f64>,
+    aty_acc: Matrix4x3<f64>,
+    n: u32,
+    min: f32,
+    max: f32,
+}
+
+impl TempSweep {
+    /// Minimum temperature span, °C, for `fit` to succeed
+    pub const MIN_SPAN: f32 = 5.;
+
+    /// dt is fitted in units of this many °C: raw, dt^6 in the normal equations reaches 1e10
+    const DT_SCALE: f64 = 32.;
+
+    /// Sweep fitting polynomials of `degree` (1..=3) around reference temperature T0 (°C)
+    pub fn new(degree: usize, reference: f32) -> Self {
+        TempSweep {
+            degree: degree.clamp(1, 3),
+            reference,
+            ata: Matrix4::zeros(),
+            aty_gyro: Matrix4x3::zeros(),
+            aty_acc: Matrix4x3::zeros(),
+            n: 0,
+            min: f32::MAX,
+            max: f32::MIN,
+        }
+    }
+
+    /// Number of recorded samples
+    pub fn len(&self) -> u32 {
+        self.n
+    }
+
+    /// Whether no samples were recorded
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Covered temperature range (min, max) in °C
+    pub fn span(&self) -> (f32, f32) {
+        (self.min, self.max)
+    }
+
+    /// Record one sample: temperature in °C, uncorrected acc in g and gyro in rad/s
+    pub fn add_sample(&mut self, temp: f32, acc: &Vector3<f32>, gyro: &Vector3<f32>) {
+        let dt = (temp - self.reference) as f64 / Self::DT_SCALE;
+        let mut row = Vector4::new(1., dt, dt * dt, dt * dt * dt);
+        for i in self.degree + 1..4 {
+            row[i] = 0.;
+        }
+
+        self.ata += row * row.transpose();
+        self.aty_gyro += row * gyro.cast::<f64>().transpose();
+        self.aty_acc += row * acc.cast::<f64>().transpose();
+        self.n += 1;
+        self.min = self.min.min(temp);
+        self.max = self.max.max(temp);
+    }
+
+    /// Fit the drift polynomials. None if the sweep covers less than `MIN_SPAN` or is degenerate
+    pub fn fit(&self) -> Option<TempCompensation> {
+        if self.n as usize <= self.degree || self.max - self.min < Self::MIN_SPAN {
+            return None;
+        }
+
+        // unused orders: identity rows keep the system regular and solve to zero
+        let mut ata = self.ata;
+        for i in self.degree + 1..4 {
+            ata[(i, i)] = 1.;
+        }
+        let inv = ata.try_inverse()?;
+
+        Some(TempCompensation {
+            reference: self.reference,
+            gyro: Self::unscale(inv * self.aty_gyro),
+            acc: Self::unscale(inv * self.aty_acc),
+        })
+    }
+
+    /// Coefficients of the scaled dt back to °C
+    fn unscale(solution: Matrix4x3<f64>) -> TempModel {
+        let mut coefficients = solution.transpose();
+        for (order, mut column) in coefficients.column_iter_mut().enumerate() {
+            column /= libm::pow(Self::DT_SCALE, order as f64);
+        }
+        TempModel { coefficients: coefficients.cast::<f32>() }
+    }
+}
+
+/// Everything needed to restore a calibrated sensor
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Calibration {
+    /// Gyro bias, rad/s, see `GyroBiasEstimator`
+    pub gyro_bias: Vector3<f32>,
+    /// Accelerometer offset, scale and misalignment, see `AccelCalibrator`
+    pub acc: AccelCalibration,
+    /// Temperature drift, see `TempSweep`
+    pub temp: Option<TempCompensation>,
+}
+
+impl Default for Calibration {
+    /// No correction at all
+    fn default() -> Self {
+        Calibration {
+            gyro_bias: Vector3::zeros(),
+            acc: AccelCalibration::default(),
+            temp: None,
+        }
+    }
+}
+
+/// Errors reading a calibration blob
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CalibrationError {
+    /// Fewer than `Calibration::SERIALIZED_LEN` bytes
+    TooShort,
+    /// Blob doesn't start with the magic bytes, e.g. erased flash
+    InvalidMagic,
+    /// Blob written by an unknown (newer) format version
+    UnsupportedVersion(u8),
+    /// Stored and calculated CRC differ
+    CrcMismatch,
+}
+
+/// CRC-16/CCITT-FALSE: poly 0x1021, init 0xffff
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff_u16, |mut crc, byte| {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+impl Calibration {
+    /// Magic bytes at the start of a blob
+    pub const MAGIC: [u8; 2] = *b"MC";
+    /// Current blob format version
+    pub const VERSION: u8 = 1;
+    /// Size of a blob in bytes
+    pub const SERIALIZED_LEN: usize = 4 + Self::FLOATS * 4 + 2;
+
+    const FLOATS: usize = 40;
+    const FLAG_TEMP: u8 = 1;
+
+    /// Serialize into a versioned blob with CRC, see module docs for the layout
+    pub fn to_bytes(&self) -> [u8; Self::SERIALIZED_LEN] {
+        let temp = self.temp.unwrap_or_default();
+        let floats = self.gyro_bias.iter()
+            .chain(self.acc.matrix.iter())
+            .chain(self.acc.bias.iter())
+            .chain(core::iter::once(&temp.reference))
+            .chain(temp.gyro.coefficients.iter())
+            .chain(temp.acc.coefficients.iter());
+
+        let mut buf = [0u8; Self::SERIALIZED_LEN];
+        buf[0..2].copy_from_slice(&Self::MAGIC);
+        buf[2] = Self::VERSION;
+        buf[3] = if self.temp.is_some() { Self::FLAG_TEMP } else { 0 };
+        for (chunk, value) in buf[4..4 + Self::FLOATS * 4].chunks_exact_mut(4).zip(floats) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+
+        let crc_at = Self::SERIALIZED_LEN - 2;
+        let crc = crc16(&buf[..crc_at]);
+        buf[crc_at..].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    /// Deserialize a blob written by `to_bytes`. Trailing bytes are ignored
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CalibrationError> {
+        if bytes.len() < Self::SERIALIZED_LEN {
+            return Err(CalibrationError::TooShort);
+        }
+        let bytes = &bytes[..Self::SERIALIZED_LEN];
+        if bytes[0..2] != Self::MAGIC {
+            return Err(CalibrationError::InvalidMagic);
+        }
+        if bytes[2] != Self::VERSION {
+            return Err(CalibrationError::UnsupportedVersion(bytes[2]));
+        }
+        let crc_at = Self::SERIALIZED_LEN - 2;
+        if crc16(&bytes[..crc_at]) != u16::from_le_bytes([bytes[crc_at], bytes[crc_at + 1]]) {
+            return Err(CalibrationError::CrcMismatch);
+        }
+
+        let mut floats = bytes[4..crc_at]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        let mut next = || floats.next().unwrap_or_default();
+
+        let gyro_bias = Vector3::from_fn(|_, _| next());
+        let matrix = Matrix3::from_fn(|_, _| next());
+        let bias = Vector3::from_fn(|_, _| next());
+        let temp = TempCompensation {
+            reference: next(),
+            gyro: TempModel { coefficients: Matrix3x4::from_fn(|_, _| next()) },
+            acc: TempModel { coefficients: Matrix3x4::from_fn(|_, _| next()) },
+        };
+
+        Ok(Calibration {
+            gyro_bias,
+            acc: AccelCalibration { matrix, bias },
+            temp: if bytes[3] & Self::FLAG_TEMP != 0 { Some(temp) } else { None },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gyro_bias_test() {
+        let mut est = GyroBiasEstimator::new(10);
+        let bias = Vector3::new(0.01, -0.02, 0.005);
+
+        // moving: rejected
+        for i in 0..10 {
+            let acc = Vector3::new(0., 0., 1. + (i % 2) as f32 * 0.2);
+            let update = est.add_sample(&acc, &bias);
+            assert_eq!(update, if i == 9 { BiasUpdate::Rejected } else { BiasUpdate::Collecting });
+        }
+        assert!(!est.is_calibrated());
+
+        // still: first window sets the bias
+        for _ in 0..10 {
+            est.add_sample(&Vector3::z(), &bias);
+        }
+        assert!(est.is_calibrated());
+        assert!((est.bias() - bias).amax() < 1e-6);
+
+        // later windows only refine
+        for _ in 0..10 {
+            est.add_sample(&Vector3::z(), &Vector3::zeros());
+        }
+        assert!((est.bias() - bias * 0.9).amax() < 1e-6);
+
+        // steady rotation: low variance, but far from the bias
+        let calibrated = est.bias();
+        for i in 0..10 {
+            let update = est.add_sample(&Vector3::z(), &Vector3::new(0., 0., 0.5));
+            assert_eq!(update, if i == 9 { BiasUpdate::Rejected } else { BiasUpdate::Collecting });
+        }
+        assert_eq!(est.bias(), calibrated);
+
+        // free fall or strong acceleration isn't rest either
+        for _ in 0..10 {
+            est.add_sample(&(Vector3::z() * 0.5), &calibrated);
+        }
+        assert_eq!(est.bias(), calibrated);
+    }
+
+    #[test]
+    fn accel_six_position_test() {
+        // 3% scale error on x, some cross axis coupling and offset
+        let a = Matrix3::new(
+            1.03, 0.01, 0.,
+            0., 0.98, 0.02,
+            -0.01, 0., 1.01,
+        );
+        let b = Vector3::new(0.05, -0.03, 0.08);
+        let mut cal = AccelCalibrator::new(5);
+
+        // unstable pose is rejected
+        for i in 0..5 {
+            let raw = a * Vector3::z() + b + Vector3::x() * (i as f32 * 0.1);
+            cal.add_sample(&raw);
+        }
+        assert_eq!(cal.next_pose(), Some(Pose::ZUp));
+
+        while let Some(pose) = cal.next_pose() {
+            let raw = a * pose.gravity() + b;
+            let mut update = PoseUpdate::Collecting;
+            for _ in 0..5 {
+                update = cal.add_sample(&raw);
+            }
+            assert_eq!(update, PoseUpdate::Accepted(pose));
+        }
+
+        let calibration = cal.solve().unwrap();
+        let raw = a * Vector3::new(0.6, 0., 0.8) + b;
+        assert!((calibration.apply(&raw) - Vector3::new(0.6, 0., 0.8)).amax() < 1e-5);
+    }
+
+    #[test]
+    fn temp_sweep_test() {
+        let mut sweep = TempSweep::new(2, 25.);
+        let gyro_bias = |t: f32| {
+            let dt = t - 25.;
+            Vector3::new(0.01 + 1e-3 * dt, -0.02 + 2e-5 * dt * dt, 0.)
+        };
+
+        for i in 0..=70 {
+            let t = -20. + i as f32;
+            sweep.add_sample(t, &Vector3::z(), &gyro_bias(t));
+        }
+        assert_eq!(sweep.span(), (-20., 50.));
+
+        let comp = sweep.fit().unwrap();
+        for t in [-10., 0., 40.] {
+            // offset at T0 is not part of the drift
+            let expected = gyro_bias(t) - gyro_bias(25.);
+            assert!((comp.gyro_drift(t) - expected).amax() < 1e-4);
+            assert!(comp.acc_drift(t).amax() < 1e-4);
+        }
+
+        let mut narrow = TempSweep::new(1, 25.);
+        narrow.add_sample(24., &Vector3::z(), &Vector3::zeros());
+        narrow.add_sample(26., &Vector3::z(), &Vector3::zeros());
+        assert!(narrow.fit().is_none());
+    }
+
+    #[test]
+    fn temp_sweep_cubic_test() {
+        let model = TempModel {
+            coefficients: Matrix3x4::new(
+                0.01, 1e-3, 2e-5, -4e-7,
+                -0.02, -5e-4, 0., 1e-6,
+                0., 0., 3e-5, 0.,
+            ),
+        };
+        let mut sweep = TempSweep::new(3, 25.);
+        for i in 0..=700 {
+            let t = -20. + i as f32 * 0.1;
+            let drift = model.coefficients * Vector4::new(1., t - 25., (t - 25.) * (t - 25.), (t - 25.) * (t - 25.) * (t - 25.));
+            sweep.add_sample(t, &(Vector3::z() + drift), &drift);
+        }
+
+        let comp = sweep.fit().unwrap();
+        let error = comp.gyro.coefficients - model.coefficients;
+        assert!(error.column(0).amax() < 1e-6);
+        assert!(error.column(1).amax() < 1e-7);
+        assert!(error.column(2).amax() < 1e-8);
+        assert!(error.column(3).amax() < 1e-10);
+        assert!((comp.acc.coefficients - model.coefficients).column(3).amax() < 1e-10);
+        for t in [-20., 0., 50.] {
+            assert!((comp.gyro_drift(t) - model.drift(t - 25.)).amax() < 1e-5);
+        }
+    }
+
+    fn example_calibration() -> Calibration {
+        let mut temp = TempCompensation::default();
+        temp.gyro.coefficients[(0, 1)] = 1e-3;
+        temp.acc.coefficients[(2, 3)] = -4e-7;
+        Calibration {
+            gyro_bias: Vector3::new(0.01, -0.02, 0.005),
+            acc: AccelCalibration {
+                matrix: Matrix3::new(1.03, 0.01, 0., 0., 0.98, 0.02, -0.01, 0., 1.01),
+                bias: Vector3::new(0.05, -0.03, 0.08),
+            },
+            temp: Some(temp),
+        }
+    }
+
+    #[test]
+    fn calibration_blob_test() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+
+        let cal = example_calibration();
+        let mut blob = cal.to_bytes();
+        assert_eq!(Calibration::from_bytes(&blob), Ok(cal));
+
+        let no_temp = Calibration { temp: None, ..cal };
+        assert_eq!(Calibration::from_bytes(&no_temp.to_bytes()), Ok(no_temp));
+
+        assert_eq!(Calibration::from_bytes(&blob[..10]), Err(CalibrationError::TooShort));
+        assert_eq!(Calibration::from_bytes(&[0xff; Calibration::SERIALIZED_LEN]), Err(CalibrationError::InvalidMagic));
+        blob[20] ^= 1;
+        assert_eq!(Calibration::from_bytes(&blob), Err(CalibrationError::CrcMismatch));
+        blob[2] = 9;
+        assert_eq!(Calibration::from_bytes(&blob), Err(CalibrationError::UnsupportedVersion(9)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn calibration_serde_test() {
+        let cal = example_calibration();
+        let json = serde_json::to_string(&cal).unwrap();
+        assert_eq!(serde_json::from_str::<Calibration>(&json).unwrap(), cal);
+    }
+}
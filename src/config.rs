@@ -0,0 +1,295 @@
+//! Validated configuration, applied in one go.
+//!
+//! ```no_run
+//! use mpu6050::{config::*, device::*};
+//! use linux_embedded_hal::{I2cdev, Delay};
+//!
+//! let mut mpu = Mpu6050Config::new()
+//!     .accel_range(AccelRange::G8)
+//!     .gyro_range(GyroRange::D1000)
+//!     .dlpf(DLPF_CFG::_44)
+//!     .sample_rate_divider(9) // 100 Hz
+//!     .interrupt_enable(1 << INT_ENABLE::DATA_RDY_EN)
+//!     .build(I2cdev::new("/dev/i2c-1").unwrap(), &mut Delay)
+//!     .unwrap();
+//! assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G8);
+//!
+//! let invalid = Mpu6050Config::new()
+//!     .clock_source(CLKSEL::GXAXIS)
+//!     .validate_for(PowerMode::LowPowerAccel(LP_WAKE_CTRL::_5));
+//! assert_eq!(invalid, Err(ConfigError::GyroClockInLowPower));
+//! ```
+
+use crate::device::*;
+use crate::{Mpu6050, Mpu6050Error};
+use crate::power::{Asleep, Awake, LowPowerAccel};
+use core::fmt;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+/// Power mode the configuration is built for, see `Mpu6050Config::validate_for`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PowerMode {
+    /// All sensors running
+    Normal,
+    /// Everything off, registers stay accessible
+    Sleep,
+    /// Gyros and temperature sensor off, single accel samples at the wake up frequency
+    LowPowerAccel(LP_WAKE_CTRL),
+}
+
+/// Invalid configurations
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConfigError {
+    /// Address is neither 0x68 nor 0x69
+    InvalidAddress(u8),
+    /// Low power accel mode turns the gyros off, so they can't be the clock source
+    GyroClockInLowPower,
+    /// Reserved or stopped clock source
+    InvalidClockSource(CLKSEL),
+    /// Reserved bits set in INT_ENABLE
+    ReservedInterruptBits(u8),
+    /// Interrupts enabled, but the device sleeps and never raises them
+    InterruptsWhileAsleep,
+    /// Multiplexer channel beyond the 8 of the TCA9548A, see `Mpu6050Group::new_with_mux`
+    InvalidMuxChannel(u8),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidAddress(address) => write!(f, "address 0x{:02x} is neither 0x68 nor 0x69", address),
+            ConfigError::GyroClockInLowPower => write!(f, "gyro clock source in low power accel mode"),
+            ConfigError::InvalidClockSource(source) => write!(f, "invalid clock source {:?}", source),
+            ConfigError::ReservedInterruptBits(bits) => write!(f, "reserved INT_ENABLE bits 0x{:02x}", bits),
+            ConfigError::InterruptsWhileAsleep => write!(f, "interrupts enabled while asleep"),
+            ConfigError::InvalidMuxChannel(channel) => write!(f, "multiplexer channel {} is not in 0 - 7", channel),
+        }
+    }
+}
+
+/// Reserved bits of INT_ENABLE
+const INT_ENABLE_RESERVED: u8 = 0b0000_0110;
+
+/// Complete device configuration, defaults to the state `Mpu6050::init` leaves the device in
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Mpu6050Config {
+    address: u8,
+    accel_range: AccelRange,
+    gyro_range: GyroRange,
+    accel_hpf: ACCEL_HPF,
+    dlpf: DLPF_CFG,
+    sample_rate_divider: u8,
+    clock_source: CLKSEL,
+    interrupt_enable: u8,
+    interrupt_pin: u8,
+}
+
+impl Default for Mpu6050Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mpu6050Config {
+    /// Address 0x68, G2, D250, no HPF, no DLPF, full sample rate, X gyro clock, no interrupts
+    pub fn new() -> Self {
+        Mpu6050Config {
+            address: DEFAULT_SLAVE_ADDR,
+            accel_range: AccelRange::G2,
+            gyro_range: GyroRange::D250,
+            accel_hpf: ACCEL_HPF::_RESET,
+            dlpf: DLPF_CFG::_260,
+            sample_rate_divider: 0,
+            clock_source: CLKSEL::GXAXIS,
+            interrupt_enable: 0,
+            interrupt_pin: 0,
+        }
+    }
+
+    /// Slave address, 0x69 if AD0 is pulled up
+    pub fn address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Accelerometer full scale range
+    pub fn accel_range(mut self, range: AccelRange) -> Self {
+        self.accel_range = range;
+        self
+    }
+
+    /// Gyro full scale range
+    pub fn gyro_range(mut self, range: GyroRange) -> Self {
+        self.gyro_range = range;
+        self
+    }
+
+    /// Accelerometer high pass filter, used by motion detection. Ignored on the MPU6500 family
+    pub fn accel_hpf(mut self, mode: ACCEL_HPF) -> Self {
+        self.accel_hpf = mode;
+        self
+    }
+
+    /// Digital low pass filter
+    pub fn dlpf(mut self, cfg: DLPF_CFG) -> Self {
+        self.dlpf = cfg;
+        self
+    }
+
+    /// Sample rate = gyro output rate / (1 + div), gyro output rate is 1 kHz with the DLPF on
+    pub fn sample_rate_divider(mut self, div: u8) -> Self {
+        self.sample_rate_divider = div;
+        self
+    }
+
+    /// Clock source
+    pub fn clock_source(mut self, source: CLKSEL) -> Self {
+        self.clock_source = source;
+        self
+    }
+
+    /// INT_ENABLE bits, e.g. `1 << INT_ENABLE::DATA_RDY_EN`
+    pub fn interrupt_enable(mut self, bits: u8) -> Self {
+        self.interrupt_enable = bits;
+        self
+    }
+
+    /// INT_PIN_CFG bits: pin level, open drain, latching, bypass
+    pub fn interrupt_pin(mut self, bits: u8) -> Self {
+        self.interrupt_pin = bits;
+        self
+    }
+
+    /// Sample rate in Hz resulting from DLPF and divider
+    pub fn sample_rate(&self) -> f32 {
+        let gyro_rate = if self.dlpf == DLPF_CFG::_260 { 8000. } else { 1000. };
+        gyro_rate / (1. + self.sample_rate_divider as f32)
+    }
+
+    /// Check for invalid combinations independent of the power mode
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.address != DEFAULT_SLAVE_ADDR && self.address != DEFAULT_SLAVE_ADDR + 1 {
+            return Err(ConfigError::InvalidAddress(self.address));
+        }
+        if matches!(self.clock_source, CLKSEL::RESERV | CLKSEL::STOP) {
+            return Err(ConfigError::InvalidClockSource(self.clock_source));
+        }
+        if self.interrupt_enable & INT_ENABLE_RESERVED != 0 {
+            return Err(ConfigError::ReservedInterruptBits(self.interrupt_enable & INT_ENABLE_RESERVED));
+        }
+        Ok(())
+    }
+
+    /// Check for invalid combinations in power mode `mode`, done by the `build` methods as well
+    pub fn validate_for(&self, mode: PowerMode) -> Result<(), ConfigError> {
+        self.validate()?;
+        let gyro_clock = matches!(self.clock_source, CLKSEL::GXAXIS | CLKSEL::GYAXIS | CLKSEL::GZAXIS);
+        if gyro_clock && matches!(mode, PowerMode::LowPowerAccel(_)) {
+            return Err(ConfigError::GyroClockInLowPower);
+        }
+        if self.interrupt_enable != 0 && mode == PowerMode::Sleep {
+            return Err(ConfigError::InterruptsWhileAsleep);
+        }
+        Ok(())
+    }
+
+    /// Validate, reset the device and apply the configuration: clock and wake up, identify the part,
+    /// sample rate, filters, ranges and interrupts. The device is left running
+    pub fn build<I2C, E, D>(self, i2c: I2C, delay: &mut D) -> Result<Mpu6050<I2C, Awake>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        D: DelayNs,
+    {
+        self.validate_for(PowerMode::Normal).map_err(Mpu6050Error::InvalidConfig)?;
+        self.apply(i2c, delay)
+    }
+
+    /// Same as `build`, then enter sleep mode
+    pub fn build_asleep<I2C, E, D>(self, i2c: I2C, delay: &mut D) -> Result<Mpu6050<I2C, Asleep>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        D: DelayNs,
+    {
+        self.validate_for(PowerMode::Sleep).map_err(Mpu6050Error::InvalidConfig)?;
+        Ok(self.apply(i2c, delay)?.into_asleep()?)
+    }
+
+    /// Same as `build`, then enter cycle mode with wake up frequency `freq`, see `Mpu6050::into_low_power_accel`
+    pub fn build_low_power<I2C, E, D>(self, i2c: I2C, delay: &mut D, freq: LP_WAKE_CTRL) -> Result<Mpu6050<I2C, LowPowerAccel>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        D: DelayNs,
+    {
+        self.validate_for(PowerMode::LowPowerAccel(freq)).map_err(Mpu6050Error::InvalidConfig)?;
+        Ok(self.apply(i2c, delay)?.into_low_power_accel(freq)?)
+    }
+
+    /// Reset the device and apply the configuration, running
+    fn apply<I2C, E, D>(self, i2c: I2C, delay: &mut D) -> Result<Mpu6050<I2C, Awake>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        D: DelayNs,
+    {
+        let mut mpu = Mpu6050::new_with_addr_and_sens(i2c, self.address, self.accel_range, self.gyro_range);
+        mpu.reset(delay)?;
+        mpu.write_byte(PWR_MGMT_1::ADDR, self.clock_source as u8)?;
+        delay.delay_ms(100u32);
+        let variant = mpu.detect_variant()?;
+
+        mpu.set_sample_rate_divider(self.sample_rate_divider)?;
+        mpu.set_dlpf(self.dlpf)?;
+        mpu.set_gyro_range(self.gyro_range)?;
+        mpu.set_accel_range(self.accel_range)?;
+        if variant.has_accel_hpf() {
+            mpu.set_accel_hpf(self.accel_hpf)?;
+        }
+        mpu.write_byte(INT_PIN_CFG::ADDR, self.interrupt_pin)?;
+        mpu.write_byte(INT_ENABLE::ADDR, self.interrupt_enable)?;
+        Ok(mpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMpu6050, NoDelay};
+
+    #[test]
+    fn config_build_test() {
+        let mut mock = MockMpu6050::new_with_addr(0x69);
+        // left over from an earlier run, cleared by the reset
+        mock.set_register(MOT_THR, 20);
+
+        let config = Mpu6050Config::new()
+            .address(0x69)
+            .accel_range(AccelRange::G4)
+            .gyro_range(GyroRange::D2000)
+            .dlpf(DLPF_CFG::_21)
+            .sample_rate_divider(4)
+            .clock_source(CLKSEL::OSCILL)
+            .interrupt_enable(1 << INT_ENABLE::MOT_EN);
+        assert_eq!(config.sample_rate(), 200.);
+
+        let mut mpu = config.build_low_power(mock, &mut NoDelay, LP_WAKE_CTRL::_10).unwrap();
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G4);
+        assert_eq!(mpu.get_gyro_range().unwrap(), GyroRange::D2000);
+        assert_eq!(mpu.get_dlpf().unwrap(), DLPF_CFG::_21);
+        assert_eq!(mpu.get_sample_rate_divider().unwrap(), 4);
+        assert_eq!(mpu.get_clock_source().unwrap(), CLKSEL::OSCILL);
+        assert_eq!(mpu.read_byte(INT_ENABLE::ADDR).unwrap(), 0x40);
+        assert_eq!(mpu.read_byte(MOT_THR).unwrap(), 0);
+        assert_eq!(mpu.get_wake_frequency().unwrap(), LP_WAKE_CTRL::_10);
+        assert!(mpu.get_cycle_enabled().unwrap() && mpu.get_gyro_standby().unwrap());
+        assert!(!mpu.get_sleep_enabled().unwrap() && !mpu.get_temp_enabled().unwrap());
+
+        assert_eq!(Mpu6050Config::new().address(0x10).validate(), Err(ConfigError::InvalidAddress(0x10)));
+        assert_eq!(Mpu6050Config::new().interrupt_enable(0xff).validate(), Err(ConfigError::ReservedInterruptBits(0x06)));
+        let interrupts = Mpu6050Config::new().interrupt_enable(1);
+        assert!(matches!(interrupts.build_asleep(MockMpu6050::new(), &mut NoDelay),
+            Err(Mpu6050Error::InvalidConfig(ConfigError::InterruptsWhileAsleep))));
+        let mut mpu = Mpu6050Config::new().build_asleep(MockMpu6050::new(), &mut NoDelay).unwrap();
+        assert!(mpu.get_sleep_enabled().unwrap());
+        assert_eq!(mpu.get_clock_source().unwrap(), CLKSEL::GXAXIS);
+    }
+}
@@ -38,6 +38,8 @@ pub const TEMP_OFFSET: f32 = 36.53;
 /// Temperature Sensitivity
 pub const TEMP_SENSITIVITY: f32 = 340.;
 
+/// Sample Rate Divider Register
+pub const SMPLRT_DIV: u8 = 0x19;
 /// Motion Threshold Register
 pub const MOT_THR: u8 = 0x1F;
 /// Motion Duration Detection Register
@@ -56,6 +58,8 @@ pub const ACC_REGY_H : u8= 0x3d;
 pub const ACC_REGZ_H : u8= 0x3f;
 /// High Byte Register Temperature
 pub const TEMP_OUT_H : u8= 0x41;
+/// High byte of the FIFO count, low byte follows
+pub const FIFO_COUNT_H: u8 = 0x72;
 /// Slave address of Mpu6050
 pub const DEFAULT_SLAVE_ADDR: u8 = 0x68;
 /// Internal register to check slave addr
@@ -144,6 +148,48 @@ impl INT_PIN_CFG {
     pub const CLKOUT_EN: u8 = 0;
 }
 
+/// Register 36: Aux I2C master control, clock bits 3:0
+pub const I2C_MST_CTRL: u8 = 0x24;
+/// Aux I2C master clock 400 kHz
+pub const I2C_MST_CLK_400: u8 = 0x0d;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 54: Aux I2C master status, cleared when read
+pub struct I2C_MST_STATUS;
+
+impl I2C_MST_STATUS {
+    /// Base Address
+    pub const ADDR: u8 = 0x36;
+    /// Slave 0 didn't acknowledge
+    pub const I2C_SLV0_NACK: u8 = 0;
+}
+
+/// Register 73: First of 24 registers with data read by the aux I2C master
+pub const EXT_SENS_DATA_00: u8 = 0x49;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 37 - 39, 99: Aux I2C slave 0
+pub struct I2C_SLV0;
+
+impl I2C_SLV0 {
+    /// Slave address, bit 7 selects read
+    pub const ADDR: u8 = 0x25;
+    /// Slave register
+    pub const REG: u8 = 0x26;
+    /// Control: enable and length
+    pub const CTRL: u8 = 0x27;
+    /// Data written to the slave
+    pub const DO: u8 = 0x63;
+    /// Read bit in ADDR
+    pub const RNW: u8 = 7;
+    /// Enable bit in CTRL
+    pub const EN: u8 = 7;
+    /// Number of bytes in CTRL
+    pub const LENG: BitBlock = BitBlock { bit: 3, length: 4 };
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 /// Register 56: Interrupt Status
@@ -228,6 +274,26 @@ impl MOT_DETECT_CONTROL {
     pub const MOT_COUNT: BitBlock = BitBlock { bit: 1, length: 2};
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 106: User Control
+pub struct USER_CTRL;
+
+impl USER_CTRL {
+    /// Base Address
+    pub const ADDR: u8 = 0x6a;
+    /// FIFO enable
+    pub const FIFO_EN: u8 = 6;
+    /// Aux I2C master enable, the aux bus is driven by the MPU instead of bypassed
+    pub const I2C_MST_EN: u8 = 5;
+    /// FIFO reset
+    pub const FIFO_RESET: u8 = 2;
+    /// Aux I2C master reset
+    pub const I2C_MST_RESET: u8 = 1;
+    /// Signal path and sensor register reset
+    pub const SIG_COND_RESET: u8 = 0;
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 /// Register 107: Power Management 1
@@ -320,6 +386,51 @@ impl From<u8> for ACCEL_HPF {
     }
 }
 
+impl From<u8> for LP_WAKE_CTRL {
+    fn from(freq: u8) -> Self {
+        match freq {
+            0 => LP_WAKE_CTRL::_1P25,
+            1 => LP_WAKE_CTRL::_2P5,
+            2 => LP_WAKE_CTRL::_5,
+            _ => LP_WAKE_CTRL::_10,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// Digital Low Pass Filter Values, accel bandwidth (gyro bandwidth is similar)
+pub enum DLPF_CFG {
+    /// 260 Hz, gyro 256 Hz, gyro output rate 8 kHz
+    _260 = 0,
+    /// 184 Hz, gyro 188 Hz
+    _184 = 1,
+    /// 94 Hz, gyro 98 Hz
+    _94 = 2,
+    /// 44 Hz, gyro 42 Hz
+    _44 = 3,
+    /// 21 Hz, gyro 20 Hz
+    _21 = 4,
+    /// 10 Hz
+    _10 = 5,
+    /// 5 Hz
+    _5 = 6,
+}
+
+impl From<u8> for DLPF_CFG {
+    fn from(cfg: u8) -> Self {
+        match cfg {
+            1 => DLPF_CFG::_184,
+            2 => DLPF_CFG::_94,
+            3 => DLPF_CFG::_44,
+            4 => DLPF_CFG::_21,
+            5 => DLPF_CFG::_10,
+            6 => DLPF_CFG::_5,
+            _ => DLPF_CFG::_260,
+        }
+    }
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 /// Clock Source Select Values
@@ -433,3 +544,89 @@ impl GyroRange {
         }
     }
 }
+
+/// Parts speaking the MPU6050 register protocol, told apart by WHO_AM_I
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChipVariant {
+    /// MPU6050, WHO_AM_I 0x68 (independent of AD0)
+    Mpu6050,
+    /// MPU6500, WHO_AM_I 0x70
+    Mpu6500,
+    /// MPU9250, MPU6500 with AK8963 magnetometer, WHO_AM_I 0x71
+    Mpu9250,
+    /// MPU9255, WHO_AM_I 0x73
+    Mpu9255,
+    /// ICM-20602, WHO_AM_I 0x12
+    Icm20602,
+    /// Unlicensed MPU6050 copies with WHO_AM_I 0x98 or 0x72, treated like the MPU6050
+    Counterfeit(u8),
+}
+
+impl ChipVariant {
+    /// Identify the part from its WHO_AM_I value
+    pub fn from_who_am_i(id: u8) -> Option<Self> {
+        match id {
+            0x68 => Some(ChipVariant::Mpu6050),
+            0x70 => Some(ChipVariant::Mpu6500),
+            0x71 => Some(ChipVariant::Mpu9250),
+            0x73 => Some(ChipVariant::Mpu9255),
+            0x12 => Some(ChipVariant::Icm20602),
+            0x98 | 0x72 => Some(ChipVariant::Counterfeit(id)),
+            _ => None,
+        }
+    }
+
+    /// WHO_AM_I value of the part
+    pub fn who_am_i(&self) -> u8 {
+        match self {
+            ChipVariant::Mpu6050 => 0x68,
+            ChipVariant::Mpu6500 => 0x70,
+            ChipVariant::Mpu9250 => 0x71,
+            ChipVariant::Mpu9255 => 0x73,
+            ChipVariant::Icm20602 => 0x12,
+            ChipVariant::Counterfeit(id) => *id,
+        }
+    }
+
+    /// Whether the part has the MPU6500 register extensions, see `mpu6500`
+    pub fn is_mpu6500_family(&self) -> bool {
+        matches!(self, ChipVariant::Mpu6500 | ChipVariant::Mpu9250 | ChipVariant::Mpu9255 | ChipVariant::Icm20602)
+    }
+
+    /// Whether the part contains an AK8963 magnetometer
+    pub fn has_magnetometer(&self) -> bool {
+        matches!(self, ChipVariant::Mpu9250 | ChipVariant::Mpu9255)
+    }
+
+    /// Whether ACCEL_CONFIG bits 2:0 are the accel high pass filter. They are reserved on the MPU6500 family
+    pub fn has_accel_hpf(&self) -> bool {
+        !self.is_mpu6500_family()
+    }
+
+    /// FIFO size in bytes
+    pub fn fifo_size(&self) -> u16 {
+        match self {
+            ChipVariant::Mpu6050 | ChipVariant::Counterfeit(_) => 1024,
+            ChipVariant::Icm20602 => 1008,
+            _ => 512,
+        }
+    }
+
+    /// Temperature sensitivity, LSB/°C
+    pub fn temp_sensitivity(&self) -> f32 {
+        match self {
+            ChipVariant::Mpu6050 | ChipVariant::Counterfeit(_) => TEMP_SENSITIVITY,
+            ChipVariant::Icm20602 => 326.8,
+            _ => 333.87,
+        }
+    }
+
+    /// Temperature at raw reading 0, °C
+    pub fn temp_offset(&self) -> f32 {
+        match self {
+            ChipVariant::Mpu6050 | ChipVariant::Counterfeit(_) => TEMP_OFFSET,
+            ChipVariant::Icm20602 => 25.,
+            _ => 21.,
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Extended Kalman filter for orientation and gyro bias.
+//!
+//! Multiplicative (error state) formulation: the orientation is kept as a unit quaternion,
+//! the filter works on a 6 element error state [attitude error (rad, sensor frame), gyro bias error (rad/s)].
+//! All matrices are fixed size, no allocation.
+//!
+//! Only the accelerometer is used as measurement, so yaw and z gyro bias of a level sensor are
+//! unobservable: their variance keeps growing, which `covariance` reports honestly.
+//!
+//! Sources:
+//! * Markley, "Attitude Error Representations for Kalman Filtering", 2003
+//! * Sola, "Quaternion kinematics for the error-state Kalman filter", 2017
+
+use crate::ahrs::Orientation;
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::i2c::I2c;
+use nalgebra::{Matrix3, Matrix6, SMatrix, UnitQuaternion, Vector3, Vector6};
+
+/// Noise parameters of the filter, all as standard deviations
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EkfNoise {
+    /// Gyro white noise density, rad/s/sqrt(Hz)
+    pub gyro: f32,
+    /// Gyro bias random walk, rad/s²/sqrt(Hz)
+    pub gyro_bias: f32,
+    /// Accelerometer measurement noise, in g
+    pub acc: f32,
+}
+
+impl Default for EkfNoise {
+    /// Rough values for a MPU6050 with default DLPF: 0.005 °/s/sqrt(Hz) gyro noise
+    /// (datasheet), generous bias walk and acc noise to cover linear acceleration
+    fn default() -> Self {
+        EkfNoise {
+            gyro: 0.005 * crate::PI_180 * 10.,
+            gyro_bias: 1e-4,
+            acc: 0.05,
+        }
+    }
+}
+
+/// Orientation and gyro bias estimator
+#[derive(Copy, Clone, Debug)]
+pub struct Ekf {
+    q: UnitQuaternion<f32>,
+    bias: Vector3<f32>,
+    p: Matrix6<f32>,
+    noise: EkfNoise,
+}
+
+impl Default for Ekf {
+    fn default() -> Self {
+        Self::new(EkfNoise::default())
+    }
+}
+
+impl Ekf {
+    /// Initial attitude uncertainty, rad
+    const INITIAL_ATTITUDE_STD: f32 = 1.;
+    /// Initial gyro bias uncertainty, rad/s
+    const INITIAL_BIAS_STD: f32 = 0.1;
+
+    /// Filter with identity orientation, zero bias and large initial uncertainty
+    pub fn new(noise: EkfNoise) -> Self {
+        let mut p = Matrix6::zeros();
+        p.fixed_view_mut::<3, 3>(0, 0).fill_diagonal(Self::INITIAL_ATTITUDE_STD * Self::INITIAL_ATTITUDE_STD);
+        p.fixed_view_mut::<3, 3>(3, 3).fill_diagonal(Self::INITIAL_BIAS_STD * Self::INITIAL_BIAS_STD);
+
+        Ekf {
+            q: UnitQuaternion::identity(),
+            bias: Vector3::zeros(),
+            p,
+            noise,
+        }
+    }
+
+    /// Noise parameters
+    pub fn noise(&self) -> EkfNoise {
+        self.noise
+    }
+
+    /// Change noise parameters, keeps state and covariance
+    pub fn set_noise(&mut self, noise: EkfNoise) {
+        self.noise = noise;
+    }
+
+    /// Estimated gyro bias, rad/s. Subtract from `get_gyro` readings
+    pub fn bias(&self) -> Vector3<f32> {
+        self.bias
+    }
+
+    /// Error state covariance, order: attitude error x y z (rad²), gyro bias x y z ((rad/s)²)
+    pub fn covariance(&self) -> Matrix6<f32> {
+        self.p
+    }
+
+    /// Standard deviation of the attitude error about the sensor x y z axes, rad
+    pub fn attitude_std(&self) -> Vector3<f32> {
+        Vector3::new(self.p[(0, 0)], self.p[(1, 1)], self.p[(2, 2)]).map(libm::sqrtf)
+    }
+
+    /// Overwrite orientation and bias, e.g. from a previous run. Covariance is kept
+    pub fn set_state(&mut self, q: UnitQuaternion<f32>, bias: Vector3<f32>) {
+        self.q = q;
+        self.bias = bias;
+    }
+
+    /// Time update with a gyro reading in rad/s over dt s
+    pub fn predict(&mut self, gyro: &Vector3<f32>, dt: f32) {
+        let rate = gyro - self.bias;
+        self.q *= UnitQuaternion::from_scaled_axis(rate * dt);
+
+        // error state transition, first order
+        let mut phi = Matrix6::identity();
+        phi.fixed_view_mut::<3, 3>(0, 0).copy_from(&(Matrix3::identity() - rate.cross_matrix() * dt));
+        phi.fixed_view_mut::<3, 3>(0, 3).copy_from(&(Matrix3::identity() * -dt));
+
+        let gyro_var = self.noise.gyro * self.noise.gyro * dt;
+        let bias_var = self.noise.gyro_bias * self.noise.gyro_bias * dt;
+        let q = Matrix6::from_diagonal(&Vector6::new(gyro_var, gyro_var, gyro_var, bias_var, bias_var, bias_var));
+
+        self.p = phi * self.p * phi.transpose() + q;
+    }
+
+    /// Measurement update with an accelerometer reading in g. Skipped if the reading is zero
+    pub fn correct(&mut self, acc: &Vector3<f32>) {
+        let a = match acc.try_normalize(0.) {
+            Some(a) => a,
+            None => return,
+        };
+
+        // predicted gravity in sensor frame and its jacobian wrt. the error state
+        let g = self.gravity();
+        let mut h = SMatrix::<f32, 3, 6>::zeros();
+        h.fixed_view_mut::<3, 3>(0, 0).copy_from(&g.cross_matrix());
+
+        let r = Matrix3::identity() * (self.noise.acc * self.noise.acc);
+        let s = h * self.p * h.transpose() + r;
+        let s_inv = match s.try_inverse() {
+            Some(s_inv) => s_inv,
+            None => return,
+        };
+        let k = self.p * h.transpose() * s_inv;
+        let dx = k * (a - g);
+
+        self.q *= UnitQuaternion::from_scaled_axis(dx.fixed_rows::<3>(0).into_owned());
+        self.bias += dx.fixed_rows::<3>(3);
+
+        // Joseph form keeps P symmetric and positive definite
+        let i_kh = Matrix6::identity() - k * h;
+        self.p = i_kh * self.p * i_kh.transpose() + k * r * k.transpose();
+    }
+
+    /// `predict` followed by `correct`: acc in g, gyro in rad/s, dt in s
+    pub fn update(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>, dt: f32) -> UnitQuaternion<f32> {
+        self.predict(gyro, dt);
+        self.correct(acc);
+        self.q
+    }
+
+    /// Read acc and gyro from the sensor and feed them to the filter, see `update`
+    pub fn update_from<I2C, E>(&mut self, mpu: &mut Mpu6050<I2C>, dt: f32) -> Result<UnitQuaternion<f32>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error=E>,
+    {
+        let acc = mpu.get_acc()?;
+        let gyro = mpu.get_gyro()?;
+        Ok(self.update(&acc, &gyro, dt))
+    }
+}
+
+impl Orientation for Ekf {
+    fn quaternion(&self) -> UnitQuaternion<f32> {
+        self.q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ekf_bias_test() {
+        let mut ekf = Ekf::default();
+        let bias = Vector3::new(0.02, -0.01, 0.005);
+        let acc = Vector3::new(0., libm::sinf(0.2), libm::cosf(0.2));
+
+        for _ in 0..5000 {
+            ekf.update(&acc, &bias, 0.01);
+        }
+
+        let (roll, pitch, _) = ekf.euler_angles();
+        assert!((roll - 0.2).abs() < 1e-2);
+        assert!(pitch.abs() < 1e-2);
+
+        // bias is observable in the plane perpendicular to gravity
+        let est = ekf.bias();
+        assert!((est.x - bias.x).abs() < 2e-3);
+
+        // heading is not observed: its uncertainty grows beyond roll/pitch
+        let std = ekf.attitude_std();
+        assert!(std.x < 0.05);
+        assert!(ekf.covariance()[(2, 2)] > ekf.covariance()[(0, 0)]);
+    }
+}
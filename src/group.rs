@@ -0,0 +1,311 @@
+//! Several sensors on one bus, e.g. at 0x68 and 0x69, or more behind a TCA9548A multiplexer.
+//!
+//! `Mpu6050Group` owns the bus and one driver state per sensor. `with_sensor` lends out a full
+//! `Mpu6050` for configuration and calibration, `read_all` reads all sensors back-to-back and
+//! timestamps each sample with `clock` (µs). Multiplexer channels are only switched when the next
+//! sensor is on another channel. Sensors without a channel are accessed with all channels off, so
+//! muxed sensors at the same address don't answer as well.
+//!
+//! ```no_run
+//! use mpu6050::group::*;
+//! use linux_embedded_hal::{I2cdev, Delay};
+//! use std::time::Instant;
+//!
+//! let start = Instant::now();
+//! let sensors = [Location::new(0x68), Location::new(0x69)];
+//! let mut group = Mpu6050Group::new(I2cdev::new("/dev/i2c-1").unwrap(), sensors, move || start.elapsed().as_micros() as u64);
+//! group.init(&mut Delay).unwrap();
+//! group.with_sensor(1, |mpu| mpu.calibrate_gyro(&mut Delay, 500, 5)).unwrap();
+//!
+//! let [upper, lower] = group.read_all().unwrap();
+//! println!("skew: {} µs", lower.timestamp_us - upper.timestamp_us);
+//! ```
+
+use crate::config::ConfigError;
+use crate::device::*;
+use crate::{Mpu6050, Mpu6050Error};
+use core::marker::PhantomData;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use nalgebra::Vector3;
+
+/// Default address of the TCA9548A multiplexer, 0x70 - 0x77 with A0 - A2
+pub const TCA9548A_ADDR: u8 = 0x70;
+
+/// Where a sensor sits on the bus
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    /// Slave address
+    pub address: u8,
+    /// Multiplexer channel 0 - 7, `None` if connected directly
+    pub channel: Option<u8>,
+}
+
+impl Location {
+    /// Sensor connected directly
+    pub fn new(address: u8) -> Self {
+        Location { address, channel: None }
+    }
+
+    /// Sensor behind multiplexer channel 0 - 7, `Mpu6050Group::new_with_mux` rejects other channels
+    pub fn muxed(channel: u8, address: u8) -> Self {
+        Location { address, channel: Some(channel) }
+    }
+}
+
+/// One sample of `read_all`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// Clock at the end of the read, µs
+    pub timestamp_us: u64,
+    /// Accelerometer in g, as `get_acc`
+    pub acc: Vector3<f32>,
+    /// Gyro in rad/s, as `get_gyro`
+    pub gyro: Vector3<f32>,
+    /// Temperature in °C
+    pub temp: f32,
+}
+
+/// Bus lent to one sensor, selects its multiplexer channel before each transaction.
+/// `selected` is the last control byte written to the multiplexer, `None` if unknown
+pub struct SensorBus<'a, I2C> {
+    i2c: &'a mut I2C,
+    mux: Option<u8>,
+    channel: Option<u8>,
+    selected: &'a mut Option<u8>,
+}
+
+impl<'a, I2C: I2c> SensorBus<'a, I2C> {
+    fn select(&mut self) -> Result<(), I2C::Error> {
+        if let Some(mux) = self.mux {
+            // all channels off for sensors connected directly
+            let control = self.channel.map_or(0, |channel| 1 << channel);
+            if *self.selected != Some(control) {
+                // unknown until the write succeeded
+                *self.selected = None;
+                self.i2c.write(mux, &[control])?;
+                *self.selected = Some(control);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, I2C: I2c> ErrorType for SensorBus<'a, I2C> {
+    type Error = I2C::Error;
+}
+
+impl<'a, I2C: I2c> I2c for SensorBus<'a, I2C> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.select()?;
+        self.i2c.transaction(address, operations)
+    }
+}
+
+/// Sensors sharing one bus, see module docs
+pub struct Mpu6050Group<I2C, C, const N: usize> {
+    i2c: I2C,
+    mux: Option<u8>,
+    locations: [Location; N],
+    drivers: [Mpu6050<()>; N],
+    selected: Option<u8>,
+    clock: C,
+}
+
+impl<I2C, E, C, const N: usize> Mpu6050Group<I2C, C, N>
+where
+    I2C: I2c<Error=E>,
+    C: FnMut() -> u64,
+{
+    /// Sensors connected directly, side effect free. `clock` timestamps samples in µs
+    pub fn new(i2c: I2C, locations: [Location; N], clock: C) -> Self {
+        Mpu6050Group {
+            i2c,
+            mux: None,
+            locations,
+            drivers: locations.map(|location| Mpu6050::<()>::parked(location.address)),
+            selected: None,
+            clock,
+        }
+    }
+
+    /// Same as `new`, with a TCA9548A at `mux` for sensors with a channel.
+    /// Fails with `ConfigError::InvalidMuxChannel` if a channel is beyond the 8 of the TCA9548A
+    pub fn new_with_mux(i2c: I2C, mux: u8, locations: [Location; N], clock: C) -> Result<Self, Mpu6050Error<E>> {
+        if let Some(channel) = locations.iter().filter_map(|location| location.channel).find(|channel| *channel >= 8) {
+            return Err(Mpu6050Error::InvalidConfig(ConfigError::InvalidMuxChannel(channel)));
+        }
+        let mut group = Self::new(i2c, locations, clock);
+        group.mux = Some(mux);
+        Ok(group)
+    }
+
+    /// Destroy the group and give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Location of sensor `index`
+    pub fn location(&self, index: usize) -> Location {
+        self.locations[index]
+    }
+
+    /// `Mpu6050::init` for all sensors
+    pub fn init<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
+        for index in 0..N {
+            self.with_sensor(index, |mpu| mpu.init(delay))?;
+        }
+        Ok(())
+    }
+
+    /// Run `f` on the driver of sensor `index`, e.g. to configure or calibrate it.
+    /// Driver state like calibration is kept for the next call and `read_all`
+    pub fn with_sensor<R, F>(&mut self, index: usize, f: F) -> R
+    where
+        F: FnOnce(&mut Mpu6050<SensorBus<'_, I2C>>) -> R,
+    {
+        let bus = SensorBus {
+            i2c: &mut self.i2c,
+            mux: self.mux,
+            channel: self.locations[index].channel,
+            selected: &mut self.selected,
+        };
+        let mut mpu = self.drivers[index].with_i2c(bus);
+        let result = f(&mut mpu);
+        self.drivers[index] = mpu.with_i2c(());
+        result
+    }
+
+    /// Read all sensors back-to-back, one burst each, and convert afterwards to keep the skew low
+    pub fn read_all(&mut self) -> Result<[Sample; N], Mpu6050Error<E>> {
+        let mut bursts = [[0; 14]; N];
+        let mut timestamps = [0; N];
+        for (index, burst) in bursts.iter_mut().enumerate() {
+            self.with_sensor(index, |mpu| mpu.read_bytes(ACC_REGX_H, burst))?;
+            timestamps[index] = (self.clock)();
+        }
+
+        let mut samples = [Sample { timestamp_us: 0, acc: Vector3::zeros(), gyro: Vector3::zeros(), temp: 0. }; N];
+        for (index, sample) in samples.iter_mut().enumerate() {
+            let (acc, gyro, temp) = self.with_sensor(index, |mpu| mpu.correct_burst(&bursts[index]));
+            *sample = Sample { timestamp_us: timestamps[index], acc, gyro, temp };
+        }
+        Ok(samples)
+    }
+}
+
+impl Mpu6050<()> {
+    /// Driver state without bus, as `Mpu6050::new_with_addr` creates it
+    fn parked(slave_addr: u8) -> Self {
+        Mpu6050 {
+            i2c: (),
+            slave_addr,
+            acc_sensitivity: ACCEL_SENS.0,
+            gyro_sensitivity: GYRO_SENS.0,
+            calibration: Default::default(),
+            gyro_bias_tracker: None,
+            register_cache: None,
+            variant: ChipVariant::Mpu6050,
+            magnetometer: None,
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<I2C, MODE> Mpu6050<I2C, MODE> {
+    /// Same driver state on another bus
+    fn with_i2c<J>(&self, i2c: J) -> Mpu6050<J, MODE> {
+        Mpu6050 {
+            i2c,
+            slave_addr: self.slave_addr,
+            acc_sensitivity: self.acc_sensitivity,
+            gyro_sensitivity: self.gyro_sensitivity,
+            calibration: self.calibration,
+            gyro_bias_tracker: self.gyro_bias_tracker,
+            register_cache: self.register_cache,
+            variant: self.variant,
+            magnetometer: self.magnetometer,
+            mode: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockError, MockMpu6050, NoDelay};
+    extern crate std;
+    use std::string::ToString;
+
+    /// Two sensors at 0x68 behind a multiplexer, one at 0x68 directly on the bus. A selected
+    /// channel shadows the direct sensor
+    struct TestBus {
+        control: u8,
+        selects: u32,
+        muxed: [MockMpu6050; 2],
+        direct: MockMpu6050,
+    }
+
+    impl ErrorType for TestBus {
+        type Error = MockError;
+    }
+
+    impl I2c for TestBus {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+            match address {
+                TCA9548A_ADDR => {
+                    if let [Operation::Write([control])] = operations {
+                        self.control = *control;
+                        self.selects += 1;
+                    }
+                    Ok(())
+                }
+                _ => match self.control {
+                    0 => self.direct.transaction(address, operations),
+                    0b01 => self.muxed[0].transaction(address, operations),
+                    0b10 => self.muxed[1].transaction(address, operations),
+                    _ => Err(MockError::NoAcknowledge),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn group_test() {
+        let mut muxed = [MockMpu6050::new(), MockMpu6050::new()];
+        muxed[0].set_acc_raw([0, 0, 16384]);
+        muxed[1].set_acc_raw([16384, 0, 0]);
+        muxed[1].set_gyro_raw([131, 0, 0]);
+        let mut direct = MockMpu6050::new();
+        direct.set_acc_raw([0, 16384, 0]);
+        let bus = TestBus { control: 0, selects: 0, muxed, direct };
+
+        let sensors = [Location::muxed(0, 0x68), Location::muxed(1, 0x68), Location::new(0x68)];
+        let mut t = 0;
+        let mut group = Mpu6050Group::new_with_mux(bus, TCA9548A_ADDR, sensors, move || { t += 10; t }).unwrap();
+        group.init(&mut NoDelay).unwrap();
+        assert_eq!(group.i2c.selects, 3);
+
+        // state set through with_sensor is kept
+        group.with_sensor(1, |mpu| mpu.set_gyro_bias(Vector3::new(crate::PI_180, 0., 0.)));
+        let [a, b, c] = group.read_all().unwrap();
+        assert_eq!(a.acc, Vector3::new(0., 0., 1.));
+        assert_eq!(b.acc, Vector3::new(1., 0., 0.));
+        assert_eq!(b.gyro, Vector3::zeros());
+        assert_eq!(c.acc, Vector3::new(0., 1., 0.));
+        assert_eq!((a.timestamp_us, b.timestamp_us, c.timestamp_us), (10, 20, 30));
+        assert_eq!(group.i2c.selects, 6);
+        assert_eq!(group.i2c.control, 0);
+
+        // bus errors are reported, the next read goes through
+        group.i2c.muxed[0].fail_next(1);
+        assert!(matches!(group.read_all(), Err(Mpu6050Error::I2c(MockError::Bus))));
+        group.read_all().unwrap();
+        assert_eq!(group.location(2), Location::new(0x68));
+
+        let bus = group.release();
+        let err = Mpu6050Group::new_with_mux(bus, TCA9548A_ADDR, [Location::muxed(8, 0x68)], || 0).err().unwrap();
+        assert!(matches!(err, Mpu6050Error::InvalidConfig(ConfigError::InvalidMuxChannel(8))));
+        assert_eq!(err.to_string(), "invalid configuration: multiplexer channel 8 is not in 0 - 7");
+    }
+}
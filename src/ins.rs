@@ -0,0 +1,208 @@
+//! Strapdown inertial navigation: velocity and position by dead reckoning.
+//!
+//! Every step:
+//! 1. propagate the orientation quaternion with the gyro rate
+//! 2. rotate specific force (`get_acc`) into the world frame (z up)
+//! 3. subtract gravity
+//! 4. integrate velocity and position with the fixed step dt
+//!
+//! Double integration of accelerometer noise and bias drifts quickly (position error grows
+//! with t³), so use zero velocity updates (ZUPT) whenever the sensor is known to be at rest,
+//! e.g. during the stance phase of a foot mounted sensor.
+//!
+//! Inputs are in the driver's units (g, rad/s), outputs in m/s and m.
+
+use crate::ahrs::Orientation;
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::i2c::I2c;
+use nalgebra::{UnitQuaternion, Vector3};
+
+/// Standard gravity in m/s², used to convert g
+pub const STANDARD_GRAVITY: f32 = 9.80665;
+
+/// Detects standstill from acc and gyro magnitude over consecutive samples
+#[derive(Copy, Clone, Debug)]
+pub struct ZuptDetector {
+    acc_tolerance: f32,
+    gyro_tolerance: f32,
+    min_samples: u16,
+    count: u16,
+}
+
+impl Default for ZuptDetector {
+    fn default() -> Self {
+        Self::new(0.05, 0.1, 10)
+    }
+}
+
+impl ZuptDetector {
+    /// Standstill if | |acc| - 1 g | < acc_tolerance (g) and |gyro| < gyro_tolerance (rad/s)
+    /// for at least min_samples consecutive samples
+    pub fn new(acc_tolerance: f32, gyro_tolerance: f32, min_samples: u16) -> Self {
+        ZuptDetector {
+            acc_tolerance,
+            gyro_tolerance,
+            min_samples,
+            count: 0,
+        }
+    }
+
+    /// Feed one sample, returns whether the sensor is considered stationary
+    pub fn update(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>) -> bool {
+        let still = (acc.norm() - 1.).abs() < self.acc_tolerance && gyro.norm() < self.gyro_tolerance;
+        self.count = if still { self.count.saturating_add(1) } else { 0 };
+        self.is_stationary()
+    }
+
+    /// Result of the last update
+    pub fn is_stationary(&self) -> bool {
+        self.count >= self.min_samples
+    }
+
+    /// Forget previous samples
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// Strapdown integrator with fixed time step
+#[derive(Copy, Clone, Debug)]
+pub struct Ins {
+    q: UnitQuaternion<f32>,
+    velocity: Vector3<f32>,
+    position: Vector3<f32>,
+    dt: f32,
+    zupt: Option<ZuptDetector>,
+    zupt_count: u32,
+}
+
+impl Ins {
+    /// Integrator at rest in the origin, level, with step dt in s (1 / sample rate)
+    pub fn new(dt: f32) -> Self {
+        Ins {
+            q: UnitQuaternion::identity(),
+            velocity: Vector3::zeros(),
+            position: Vector3::zeros(),
+            dt,
+            zupt: None,
+            zupt_count: 0,
+        }
+    }
+
+    /// Same as `new`, but zero velocity updates are applied automatically when `detector` reports standstill
+    pub fn new_with_zupt(dt: f32, detector: ZuptDetector) -> Self {
+        let mut ins = Self::new(dt);
+        ins.zupt = Some(detector);
+        ins
+    }
+
+    /// Set starting orientation (sensor to world frame), e.g. from an AHRS filter at rest
+    pub fn set_orientation(&mut self, q: UnitQuaternion<f32>) {
+        self.q = q;
+    }
+
+    /// Set position in m
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.position = position;
+    }
+
+    /// Set velocity in m/s
+    pub fn set_velocity(&mut self, velocity: Vector3<f32>) {
+        self.velocity = velocity;
+    }
+
+    /// Velocity in m/s, world frame
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    /// Position in m, world frame
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    /// Time step in s
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    /// Number of zero velocity updates applied so far, saturating at `u32::MAX`
+    pub fn zupt_count(&self) -> u32 {
+        self.zupt_count
+    }
+
+    /// Zero velocity update: the caller knows the sensor is at rest
+    pub fn zupt(&mut self) {
+        self.velocity = Vector3::zeros();
+        self.zupt_count = self.zupt_count.saturating_add(1);
+    }
+
+    /// One integration step: acc in g, gyro in rad/s. Returns the world frame linear acceleration in m/s²
+    pub fn step(&mut self, acc: &Vector3<f32>, gyro: &Vector3<f32>) -> Vector3<f32> {
+        let dt = self.dt;
+        self.q *= UnitQuaternion::from_scaled_axis(gyro * dt);
+
+        let specific_force = self.q.transform_vector(acc) * STANDARD_GRAVITY;
+        let linear = specific_force - Vector3::z() * STANDARD_GRAVITY;
+
+        self.position += self.velocity * dt + linear * (0.5 * dt * dt);
+        self.velocity += linear * dt;
+
+        let stationary = match self.zupt.as_mut() {
+            Some(detector) => detector.update(acc, gyro),
+            None => false,
+        };
+        if stationary {
+            self.zupt();
+        }
+
+        linear
+    }
+
+    /// Read acc and gyro from the sensor and integrate them, see `step`
+    pub fn step_from<I2C, E>(&mut self, mpu: &mut Mpu6050<I2C>) -> Result<Vector3<f32>, Mpu6050Error<E>>
+    where
+        I2C: I2c<Error=E>,
+    {
+        let acc = mpu.get_acc()?;
+        let gyro = mpu.get_gyro()?;
+        Ok(self.step(&acc, &gyro))
+    }
+}
+
+impl Orientation for Ins {
+    fn quaternion(&self) -> UnitQuaternion<f32> {
+        self.q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ins_integration_test() {
+        let mut ins = Ins::new(0.01);
+        // 1 s with 0.1 g forward, level
+        for _ in 0..100 {
+            ins.step(&Vector3::new(0.1, 0., 1.), &Vector3::zeros());
+        }
+        let v = 0.1 * STANDARD_GRAVITY;
+        assert!((ins.velocity().x - v).abs() < 1e-3);
+        assert!((ins.position().x - 0.5 * v).abs() < 1e-3);
+        assert!(ins.position().z.abs() < 1e-4);
+    }
+
+    #[test]
+    fn zupt_test() {
+        let mut ins = Ins::new_with_zupt(0.01, ZuptDetector::new(0.05, 0.1, 5));
+        ins.set_velocity(Vector3::new(1., 0., 0.));
+        for _ in 0..4 {
+            ins.step(&Vector3::z(), &Vector3::zeros());
+        }
+        assert_eq!(ins.zupt_count(), 0);
+        ins.step(&Vector3::z(), &Vector3::zeros());
+        assert_eq!(ins.zupt_count(), 1);
+        assert_eq!(ins.velocity(), Vector3::zeros());
+    }
+}
@@ -44,18 +44,61 @@
 //! }
 //! 
 //! ```
+//!
+//! ### Sharing the bus
+//! The driver takes any `I2c`: a bus by value (`release` gives it back), a mutable borrow
+//! (`new_borrowed`), or a shared bus device of [`embedded-hal-bus`](https://docs.rs/embedded-hal-bus),
+//! e.g. `RefCellDevice`, `CriticalSectionDevice` or `MutexDevice`, next to other sensors.
+//! ```no_run
+//! use mpu6050::*;
+//! use linux_embedded_hal::{I2cdev, Delay};
+//! use embedded_hal_bus::i2c::RefCellDevice;
+//! use core::cell::RefCell;
+//!
+//! let bus = RefCell::new(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut mpu = Mpu6050::new(RefCellDevice::new(&bus));
+//! let mut barometer = RefCellDevice::new(&bus); // e.g. handed to a barometer driver
+//! mpu.init(&mut Delay).unwrap();
+//! ```
 
 #![no_std]
 
 mod bits;
 pub mod device;
+pub mod attitude;
+pub mod ahrs;
+pub mod ekf;
+pub mod linear;
+pub mod ins;
+pub mod calibration;
+pub mod trace;
+pub mod registers;
+pub mod config;
+pub mod power;
+pub mod mpu6500;
+pub mod ak8963;
+pub mod group;
+pub mod recovery;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+#[cfg(any(test, feature = "mock"))]
+pub mod simulator;
+#[cfg(feature = "units")]
+pub mod units;
 
 use crate::device::*;
-use libm::{powf, atan2f, sqrtf};
+use crate::registers::RegisterCache;
+use crate::config::ConfigError;
+use crate::ak8963::{MagError, Magnetometer};
+use crate::mpu6500::SelfTest;
+use crate::power::{AccelAvailable, Asleep, Awake, Transition};
+use core::fmt;
+use core::marker::PhantomData;
+use crate::calibration::{AccelCalibration, AccelCalibrator, BiasUpdate, Calibration, GyroBiasEstimator, PoseUpdate, TempCompensation, TempSweep};
 use nalgebra::{Vector3, Vector2};
 use embedded_hal::{
     delay::DelayNs,
-    i2c::I2c,
+    i2c::{ErrorKind, I2c, NoAcknowledgeSource},
 };
 
 
@@ -73,27 +116,106 @@ pub enum Mpu6050Error<E> {
 
     /// Invalid chip ID was read
     InvalidChipId(u8),
+
+    /// Rejected configuration, see `Mpu6050Config::validate_for`
+    InvalidConfig(ConfigError),
+
+    /// Register extension not available on the detected part, see `mpu6500`
+    Unsupported(ChipVariant),
+
+    /// AK8963 magnetometer error, see `ak8963`
+    Magnetometer(MagError),
+
+    /// Self test response out of tolerance, see `Mpu6050::self_test`
+    SelfTestFailed(SelfTest),
+
+    /// FIFO filled up and samples were lost, reset the FIFO to recover. `count` as read from FIFO_COUNT
+    FifoOverflow { count: u16 },
+
+    /// No still window found in `calibrate_gyro` after `attempts` tries
+    CalibrationNotConverged { attempts: u8 },
+
+    /// No new sample flagged in register `reg` in time
+    DataNotReady { reg: u8 },
+
+    /// Magnetometer reading exceeded the measurement range and is invalid, `reg` is the status
+    /// register that flagged it. Accel and gyro readings clip at ±32767 without error
+    Saturated { reg: u8 },
+
+    /// Slave at `address` didn't acknowledge an aux I2C master transfer of register `reg`
+    AuxNack { address: u8, reg: u8 },
+}
+
+impl<E: embedded_hal::i2c::Error> Mpu6050Error<E> {
+    /// Bus error kind, e.g. to tell a NACK from arbitration loss. An aux master NACK counts as address NACK,
+    /// `None` for errors that aren't bus errors
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Mpu6050Error::I2c(e) => Some(e.kind()),
+            Mpu6050Error::AuxNack { .. } => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            _ => None,
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Mpu6050Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mpu6050Error::I2c(e) => write!(f, "I2C bus error: {:?}", e),
+            Mpu6050Error::InvalidChipId(id) => write!(f, "invalid chip id 0x{:02x}", id),
+            Mpu6050Error::InvalidConfig(e) => write!(f, "invalid configuration: {}", e),
+            Mpu6050Error::Unsupported(variant) => write!(f, "not supported by {:?}", variant),
+            Mpu6050Error::Magnetometer(e) => write!(f, "magnetometer: {}", e),
+            Mpu6050Error::SelfTestFailed(result) => write!(f, "self test failed (gyro {}, accel {})",
+                if result.gyro_passed() { "passed" } else { "failed" },
+                if result.accel_passed() { "passed" } else { "failed" }),
+            Mpu6050Error::FifoOverflow { count } => write!(f, "FIFO overflow at {} bytes", count),
+            Mpu6050Error::CalibrationNotConverged { attempts } =>
+                write!(f, "gyro calibration found no still window in {} attempts", attempts),
+            Mpu6050Error::DataNotReady { reg } => write!(f, "data not ready in register 0x{:02x}", reg),
+            Mpu6050Error::Saturated { reg } => write!(f, "magnetometer saturated, register 0x{:02x}", reg),
+            Mpu6050Error::AuxNack { address, reg } =>
+                write!(f, "aux I2C slave 0x{:02x} didn't acknowledge register 0x{:02x}", address, reg),
+        }
+    }
 }
 
-/// Handles all operations on/with Mpu6050
-pub struct Mpu6050<I2C> {
+impl<E: fmt::Debug> core::error::Error for Mpu6050Error<E> {}
+
+/// Handles all operations on/with Mpu6050. `MODE` is the power state, see `power`
+pub struct Mpu6050<I2C, MODE = Awake> {
     i2c: I2C,
     slave_addr: u8,
     acc_sensitivity: f32,
     gyro_sensitivity: f32,
+    calibration: Calibration,
+    gyro_bias_tracker: Option<GyroBiasEstimator>,
+    register_cache: Option<RegisterCache>,
+    variant: ChipVariant,
+    magnetometer: Option<Magnetometer>,
+    mode: PhantomData<MODE>,
 }
 
 impl<I2C, E> Mpu6050<I2C>
 where
-    I2C: I2c<Error=E>, 
+    I2C: I2c<Error=E>,
 {
-    /// Side effect free constructor with default sensitivies, no calibration
+    /// Side effect free constructor with default sensitivies, no calibration.
+    /// The driver is typed `Awake`, although the chip powers up asleep: run `init` before reading data.
+    /// This and the other `new_*` constructors are the one unchecked entry into the power states,
+    /// `new_asleep` followed by `into_awake` is the checked one
     pub fn new(i2c: I2C) -> Self {
         Mpu6050 {
             i2c,
             slave_addr: DEFAULT_SLAVE_ADDR,
             acc_sensitivity: ACCEL_SENS.0,
             gyro_sensitivity: GYRO_SENS.0,
+            calibration: Calibration::default(),
+            gyro_bias_tracker: None,
+            register_cache: None,
+            variant: ChipVariant::Mpu6050,
+            magnetometer: None,
+            mode: PhantomData,
         }
     }
 
@@ -104,6 +226,12 @@ where
             slave_addr: DEFAULT_SLAVE_ADDR,
             acc_sensitivity: arange.sensitivity(),
             gyro_sensitivity: grange.sensitivity(),
+            calibration: Calibration::default(),
+            gyro_bias_tracker: None,
+            register_cache: None,
+            variant: ChipVariant::Mpu6050,
+            magnetometer: None,
+            mode: PhantomData,
         }
     }
 
@@ -114,6 +242,12 @@ where
             slave_addr,
             acc_sensitivity: ACCEL_SENS.0,
             gyro_sensitivity: GYRO_SENS.0,
+            calibration: Calibration::default(),
+            gyro_bias_tracker: None,
+            register_cache: None,
+            variant: ChipVariant::Mpu6050,
+            magnetometer: None,
+            mode: PhantomData,
         }
     }
 
@@ -124,29 +258,75 @@ where
             slave_addr,
             acc_sensitivity: arange.sensitivity(),
             gyro_sensitivity: grange.sensitivity(),
+            calibration: Calibration::default(),
+            gyro_bias_tracker: None,
+            register_cache: None,
+            variant: ChipVariant::Mpu6050,
+            magnetometer: None,
+            mode: PhantomData,
         }
     }
 
-    /// Wakes MPU6050 with all sensors enabled (default)
-    fn wake<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
-        // MPU6050 has sleep enabled by default -> set bit 0 to wake
-        // Set clock source to be PLL with x-axis gyroscope reference, bits 2:0 = 001 (See Register Map )
-        self.write_byte(PWR_MGMT_1::ADDR, 0x01)?;
-        delay.delay_ms(100u32);
-        Ok(())
+    /// Same as `new`, but with a stored calibration, see `Calibration::from_bytes`
+    pub fn new_with_calibration(i2c: I2C, calibration: Calibration) -> Self {
+        let mut mpu = Self::new(i2c);
+        mpu.calibration = calibration;
+        mpu
     }
 
-    /// From Register map:
-    /// "An  internal  8MHz  oscillator,  gyroscope based  clock,or  external  sources  can  be
-    /// selected  as the MPU-60X0 clock source.
-    /// When the internal 8 MHz oscillator or an external source is chosen as the clock source,
-    /// the MPU-60X0 can operate in low power modes with the gyroscopes disabled. Upon power up,
-    /// the MPU-60X0clock source defaults to the internal oscillator. However, it is highly
-    /// recommended  that  the  device beconfigured  to  use  one  of  the  gyroscopes
-    /// (or  an  external  clocksource) as the clock reference for improved stability.
-    /// The clock source can be selected according to the following table...."
-    pub fn set_clock_source(&mut self, source: CLKSEL) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bits(PWR_MGMT_1::ADDR, PWR_MGMT_1::CLKSEL.bit, PWR_MGMT_1::CLKSEL.length, source as u8)?)
+    /// Same as `new_with_sens`, but with a stored calibration, see `Calibration::from_bytes`
+    pub fn new_with_sens_and_calibration(i2c: I2C, arange: AccelRange, grange: GyroRange, calibration: Calibration) -> Self {
+        let mut mpu = Self::new_with_sens(i2c, arange, grange);
+        mpu.calibration = calibration;
+        mpu
+    }
+
+    /// Same as `new_with_addr_and_sens`, but with a stored calibration, see `Calibration::from_bytes`
+    pub fn new_with_addr_sens_and_calibration(i2c: I2C, slave_addr: u8, arange: AccelRange, grange: GyroRange, calibration: Calibration) -> Self {
+        let mut mpu = Self::new_with_addr_and_sens(i2c, slave_addr, arange, grange);
+        mpu.calibration = calibration;
+        mpu
+    }
+}
+
+impl<'a, I2C, E> Mpu6050<&'a mut I2C>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Driver on a borrowed bus, usable by other drivers again once this one is dropped
+    pub fn new_borrowed(i2c: &'a mut I2C) -> Self {
+        Mpu6050::new(i2c)
+    }
+
+    /// Same as `new_borrowed`, with a custom chip address
+    pub fn new_borrowed_with_addr(i2c: &'a mut I2C, slave_addr: u8) -> Self {
+        Mpu6050::new_with_addr(i2c, slave_addr)
+    }
+}
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Destroy the driver and give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// All corrections applied in `get_acc` and `get_gyro`, e.g. to persist with `Calibration::to_bytes`
+    pub fn get_calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    /// Replace all corrections applied in `get_acc` and `get_gyro`. Also seeds the bias tracker, if enabled
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+        self.set_gyro_bias(calibration.gyro_bias);
+    }
+
+    /// Writes PWR_MGMT_1 CLKSEL in any power state, see `set_clock_source`
+    pub(crate) fn write_clock_source(&mut self, source: CLKSEL) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(PWR_MGMT_1::ADDR, PWR_MGMT_1::CLKSEL.bit, PWR_MGMT_1::CLKSEL.length, source as u8)
     }
 
     /// get current clock source
@@ -155,39 +335,18 @@ where
         Ok(CLKSEL::from(source))
     }
 
-    /// Init wakes MPU6050 and verifies register addr, e.g. in i2c
-    pub fn init<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
-        self.wake(delay)?;
-        self.verify()?;
-        self.set_accel_range(AccelRange::G2)?;
-        self.set_gyro_range(GyroRange::D250)?;
-        self.set_accel_hpf(ACCEL_HPF::_RESET)?;
-        Ok(())
-    }
-
-    /// Verifies device to address 0x68 with WHOAMI.addr() Register
-    fn verify(&mut self) -> Result<(), Mpu6050Error<E>> {
-        let address = self.read_byte(WHOAMI)?;
-        if address != DEFAULT_SLAVE_ADDR {
-            return Err(Mpu6050Error::InvalidChipId(address));
-        }
-        Ok(())
+    /// Identifies the part by its WHO_AM_I register and adapts temperature scaling to it.
+    /// Unknown parts fail with `InvalidChipId`
+    pub fn detect_variant(&mut self) -> Result<ChipVariant, Mpu6050Error<E>> {
+        let id = self.read_byte(WHOAMI)?;
+        let variant = ChipVariant::from_who_am_i(id).ok_or(Mpu6050Error::InvalidChipId(id))?;
+        self.variant = variant;
+        Ok(variant)
     }
 
-    /// setup motion detection
-    /// sources:
-    /// * https://github.com/kriswiner/MPU6050/blob/a7e0c8ba61a56c5326b2bcd64bc81ab72ee4616b/MPU6050IMU.ino#L486
-    /// * https://arduino.stackexchange.com/a/48430
-    pub fn setup_motion_detection(&mut self) -> Result<(), Mpu6050Error<E>> {
-        self.write_byte(0x6B, 0x00)?;
-        // optional? self.write_byte(0x68, 0x07)?; // Reset all internal signal paths in the MPU-6050 by writing 0x07 to register 0x68;
-        self.write_byte(INT_PIN_CFG::ADDR, 0x20)?; //write register 0x37 to select how to use the interrupt pin. For an active high, push-pull signal that stays until register (decimal) 58 is read, write 0x20.
-        self.write_byte(ACCEL_CONFIG::ADDR, 0x01)?; //Write register 28 (==0x1C) to set the Digital High Pass Filter, bits 3:0. For example set it to 0x01 for 5Hz. (These 3 bits are grey in the data sheet, but they are used! Leaving them 0 means the filter always outputs 0.)
-        self.write_byte(MOT_THR, 10)?; //Write the desired Motion threshold to register 0x1F (For example, write decimal 20).
-        self.write_byte(MOT_DUR, 40)?; //Set motion detect duration to 1  ms; LSB is 1 ms @ 1 kHz rate
-        self.write_byte(0x69, 0x15)?; //to register 0x69, write the motion detection decrement and a few other settings (for example write 0x15 to set both free-fall and motion decrements to 1 and accelerometer start-up delay to 5ms total by adding 1ms. )
-        self.write_byte(INT_ENABLE::ADDR, 0x40)?; //write register 0x38, bit 6 (0x40), to enable motion detection interrupt.
-        Ok(())
+    /// Part found by `detect_variant` (`init` runs it), `ChipVariant::Mpu6050` before
+    pub fn get_variant(&self) -> ChipVariant {
+        self.variant
     }
 
     /// get whether or not motion has been detected (INT_STATUS, MOT_INT)
@@ -197,12 +356,10 @@ where
 
     /// set accel high pass filter mode
     pub fn set_accel_hpf(&mut self, mode: ACCEL_HPF) -> Result<(), Mpu6050Error<E>> {
-        Ok(
-            self.write_bits(ACCEL_CONFIG::ADDR,
-                            ACCEL_CONFIG::ACCEL_HPF.bit,
-                            ACCEL_CONFIG::ACCEL_HPF.length,
-                            mode as u8)?
-        )
+        self.write_bits(ACCEL_CONFIG::ADDR,
+                        ACCEL_CONFIG::ACCEL_HPF.bit,
+                        ACCEL_CONFIG::ACCEL_HPF.length,
+                        mode as u8)
     }
 
     /// get accel high pass filter mode
@@ -254,8 +411,100 @@ where
         Ok(AccelRange::from(byte))
     }
 
-    /// reset device
-    pub fn reset_device<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
+    /// set digital low pass filter, also selects the gyro output rate (8 kHz for `_260`, 1 kHz otherwise)
+    pub fn set_dlpf(&mut self, cfg: DLPF_CFG) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(CONFIG::ADDR, CONFIG::DLPF_CFG.bit, CONFIG::DLPF_CFG.length, cfg as u8)
+    }
+
+    /// get digital low pass filter
+    pub fn get_dlpf(&mut self) -> Result<DLPF_CFG, Mpu6050Error<E>> {
+        Ok(DLPF_CFG::from(self.read_bits(CONFIG::ADDR, CONFIG::DLPF_CFG.bit, CONFIG::DLPF_CFG.length)?))
+    }
+
+    /// set sample rate divider: sample rate = gyro output rate / (1 + div)
+    pub fn set_sample_rate_divider(&mut self, div: u8) -> Result<(), Mpu6050Error<E>> {
+        self.write_byte(SMPLRT_DIV, div)
+    }
+
+    /// get sample rate divider
+    pub fn get_sample_rate_divider(&mut self) -> Result<u8, Mpu6050Error<E>> {
+        self.read_byte(SMPLRT_DIV)
+    }
+
+    /// enable, disable cycle mode: sleep and wake up for single accel samples, see `set_wake_frequency`
+    pub(crate) fn set_cycle_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
+        self.write_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::CYCLE, enable)
+    }
+
+    /// get cycle mode status
+    pub fn get_cycle_enabled(&mut self) -> Result<bool, Mpu6050Error<E>> {
+        Ok(self.read_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::CYCLE)? != 0)
+    }
+
+    /// set wake up frequency in cycle mode
+    pub fn set_wake_frequency(&mut self, freq: LP_WAKE_CTRL) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(PWR_MGMT_2::ADDR, PWR_MGMT_2::LP_WAKE_CTRL.bit, PWR_MGMT_2::LP_WAKE_CTRL.length, freq as u8)
+    }
+
+    /// get wake up frequency in cycle mode
+    pub fn get_wake_frequency(&mut self) -> Result<LP_WAKE_CTRL, Mpu6050Error<E>> {
+        Ok(LP_WAKE_CTRL::from(self.read_bits(PWR_MGMT_2::ADDR, PWR_MGMT_2::LP_WAKE_CTRL.bit, PWR_MGMT_2::LP_WAKE_CTRL.length)?))
+    }
+
+    /// put all gyro axes in standby or enable them
+    pub(crate) fn set_gyro_standby(&mut self, standby: bool) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(PWR_MGMT_2::ADDR, PWR_MGMT_2::STBY_XG, 3, if standby { 0b111 } else { 0 })
+    }
+
+    /// whether all gyro axes are in standby
+    pub fn get_gyro_standby(&mut self) -> Result<bool, Mpu6050Error<E>> {
+        Ok(self.read_bits(PWR_MGMT_2::ADDR, PWR_MGMT_2::STBY_XG, 3)? == 0b111)
+    }
+
+    /// number of bytes in the FIFO, up to `ChipVariant::fifo_size`
+    pub fn get_fifo_count(&mut self) -> Result<u16, Mpu6050Error<E>> {
+        let mut buf = [0; 2];
+        self.read_bytes(FIFO_COUNT_H, &mut buf)?;
+        Ok(u16::from_be_bytes(buf) & 0x1fff)
+    }
+
+    /// Read up to `buf.len()` bytes from the FIFO, returns the number of bytes read.
+    /// Fails with `FifoOverflow` if INT_STATUS flags an overflow (FIFO_OFLOW_EN in INT_ENABLE), the
+    /// oldest samples were overwritten then. Reading INT_STATUS clears all interrupt flags
+    pub fn read_fifo(&mut self, buf: &mut [u8]) -> Result<usize, Mpu6050Error<E>> {
+        let overflow = self.read_bit(INT_STATUS::ADDR, INT_STATUS::FIFO_OFLOW_INT)? != 0;
+        let count = self.get_fifo_count()?;
+        if overflow {
+            return Err(Mpu6050Error::FifoOverflow { count });
+        }
+        let len = buf.len().min(count as usize);
+        if len > 0 {
+            self.read_bytes(registers::FIFO_R_W, &mut buf[..len])?;
+        }
+        Ok(len)
+    }
+
+    /// Poll INT_STATUS every ms until a new sample is ready, for up to `timeout_ms`.
+    /// Note that reading INT_STATUS clears all interrupt flags
+    pub fn wait_data_ready<D: DelayNs>(&mut self, delay: &mut D, timeout_ms: u32) -> Result<(), Mpu6050Error<E>> {
+        for _ in 0..=timeout_ms {
+            if self.read_bit(INT_STATUS::ADDR, INT_STATUS::DATA_RDY_INT)? != 0 {
+                return Ok(());
+            }
+            delay.delay_ms(1);
+        }
+        Err(Mpu6050Error::DataNotReady { reg: INT_STATUS::ADDR })
+    }
+
+    /// reset device, all registers return to their reset values. The chip is asleep afterwards
+    // the error hands back the driver, see `power`
+    #[allow(clippy::result_large_err)]
+    pub fn reset_device<D: DelayNs>(self, delay: &mut D) -> Transition<I2C, MODE, Asleep, E> {
+        self.transition(|mpu| mpu.reset(delay))
+    }
+
+    /// reset device without changing the type, for callers that wake the chip right after
+    pub(crate) fn reset<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
         self.write_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::DEVICE_RESET, true)?;
         delay.delay_ms(100u32);
         // Note: Reset sets sleep to true! Section register map: resets PWR_MGMT to 0x40
@@ -264,7 +513,7 @@ where
 
     /// enable, disable i2c master interrupt
     pub fn set_master_interrupt_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(INT_ENABLE::ADDR, INT_ENABLE::I2C_MST_INT_EN, enable)?)
+        self.write_bit(INT_ENABLE::ADDR, INT_ENABLE::I2C_MST_INT_EN, enable)
     }
 
     /// get i2c master interrupt status
@@ -274,7 +523,7 @@ where
 
     /// enable, disable bypass of sensor
     pub fn set_bypass_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(INT_PIN_CFG::ADDR, INT_PIN_CFG::I2C_BYPASS_EN, enable)?)
+        self.write_bit(INT_PIN_CFG::ADDR, INT_PIN_CFG::I2C_BYPASS_EN, enable)
     }
 
     /// get bypass status
@@ -283,8 +532,8 @@ where
     }
 
     /// enable, disable sleep of sensor
-    pub fn set_sleep_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::SLEEP, enable)?)
+    pub(crate) fn set_sleep_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
+        self.write_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::SLEEP, enable)
     }
 
     /// get sleep status
@@ -292,11 +541,9 @@ where
         Ok(self.read_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::SLEEP)? != 0)
     }
 
-    /// enable, disable temperature measurement of sensor
-    /// TEMP_DIS actually saves "disabled status"
-    /// 1 is disabled! -> enable=true : bit=!enable
-    pub fn set_temp_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::TEMP_DIS, !enable)?)
+    /// Writes PWR_MGMT_1 TEMP_DIS in any power state, see `set_temp_enabled`
+    pub(crate) fn write_temp_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
+        self.write_bit(PWR_MGMT_1::ADDR, PWR_MGMT_1::TEMP_DIS, !enable)
     }
 
     /// get temperature sensor status
@@ -308,7 +555,7 @@ where
 
     /// set accel x self test
     pub fn set_accel_x_self_test(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::XA_ST, enable)?)
+        self.write_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::XA_ST, enable)
     }
 
     /// get accel x self test
@@ -318,7 +565,7 @@ where
 
     /// set accel y self test
     pub fn set_accel_y_self_test(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::YA_ST, enable)?)
+        self.write_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::YA_ST, enable)
     }
 
     /// get accel y self test
@@ -328,7 +575,7 @@ where
 
     /// set accel z self test
     pub fn set_accel_z_self_test(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        Ok(self.write_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::ZA_ST, enable)?)
+        self.write_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::ZA_ST, enable)
     }
 
     /// get accel z self test
@@ -336,16 +583,36 @@ where
         Ok(self.read_bit(ACCEL_CONFIG::ADDR, ACCEL_CONFIG::ZA_ST)? != 0)
     }
 
-    /// Roll and pitch estimation from raw accelerometer readings
-    /// NOTE: no yaw! no magnetometer present on MPU6050
-    /// https://www.nxp.com/docs/en/application-note/AN3461.pdf equation 28, 29
-    pub fn get_acc_angles(&mut self) -> Result<Vector2<f32>, Mpu6050Error<E>> {
-        let acc = self.get_acc()?;
+    /// Sensor Temp in degrees celcius, in any power state. Used for temperature compensation
+    fn read_temp(&mut self) -> Result<f32, Mpu6050Error<E>> {
+        let mut buf: [u8; 2] = [0; 2];
+        self.read_bytes(TEMP_OUT_H, &mut buf)?;
+        let raw_temp = self.read_word_2c(&buf[0..2]) as f32;
 
-        Ok(Vector2::<f32>::new(
-            atan2f(acc.y, sqrtf(powf(acc.x, 2.) + powf(acc.z, 2.))),
-            atan2f(-acc.x, sqrtf(powf(acc.y, 2.) + powf(acc.z, 2.)))
-        ))
+        // According to revision 4.2, other parts see ChipVariant
+        Ok((raw_temp / self.variant.temp_sensitivity()) + self.variant.temp_offset())
+    }
+
+    /// Accel in g, gyro in rad/s and temperature in °C from one burst read of ACCEL_XOUT_H to
+    /// GYRO_ZOUT_L, with the corrections of `get_acc` and `get_gyro`
+    pub(crate) fn correct_burst(&mut self, buf: &[u8; 14]) -> (Vector3<f32>, Vector3<f32>, f32) {
+        let word = |i: usize| self.read_word_2c(&buf[i..i + 2]) as f32;
+        let temp = word(6) / self.variant.temp_sensitivity() + self.variant.temp_offset();
+        let mut acc = Vector3::new(word(0), word(2), word(4)) / self.acc_sensitivity;
+        let mut gyro = Vector3::new(word(8), word(10), word(12)) * (PI_180 / self.gyro_sensitivity);
+
+        if let Some(comp) = self.calibration.temp {
+            acc -= comp.acc_drift(temp);
+            gyro -= comp.gyro_drift(temp);
+        }
+        let acc = self.calibration.acc.apply(&acc);
+
+        if let Some(tracker) = self.gyro_bias_tracker.as_mut() {
+            if tracker.add_sample(&acc, &gyro) == BiasUpdate::Updated {
+                self.calibration.gyro_bias = tracker.bias();
+            }
+        }
+        (acc, gyro - self.calibration.gyro_bias, temp)
     }
 
     /// Converts 2 bytes number in 2 compliment
@@ -362,90 +629,106 @@ where
         word
     }
 
-    /// Reads rotation (gyro/acc) from specified register
-    fn read_rot(&mut self, reg: u8) -> Result<Vector3<f32>, Mpu6050Error<E>> {
-        let mut buf: [u8; 6] = [0; 6];
-        self.read_bytes(reg, &mut buf)?;
-
-        Ok(Vector3::<f32>::new(
-            self.read_word_2c(&buf[0..2]) as f32,
-            self.read_word_2c(&buf[2..4]) as f32,
-            self.read_word_2c(&buf[4..6]) as f32
-        ))
+    /// Offset, scale and misalignment correction applied in `get_acc`
+    pub fn get_accel_calibration(&self) -> AccelCalibration {
+        self.calibration.acc
     }
 
-    /// Accelerometer readings in g
-    pub fn get_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
-        let mut acc = self.read_rot(ACC_REGX_H)?;
-        acc /= self.acc_sensitivity;
-
-        Ok(acc)
+    /// Set offset, scale and misalignment correction applied in `get_acc`, e.g. from `AccelCalibrator::solve`
+    pub fn set_accel_calibration(&mut self, calibration: AccelCalibration) {
+        self.calibration.acc = calibration;
     }
 
-    /// Gyro readings in rad/s
-    pub fn get_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
-        let mut gyro = self.read_rot(GYRO_REGX_H)?;
+    /// Gyro bias subtracted in `get_gyro`, rad/s
+    pub fn get_gyro_bias(&self) -> Vector3<f32> {
+        self.calibration.gyro_bias
+    }
 
-        gyro *= PI_180 / self.gyro_sensitivity;
+    /// Set gyro bias subtracted in `get_gyro`, rad/s. Also seeds the bias tracker, if enabled
+    pub fn set_gyro_bias(&mut self, bias: Vector3<f32>) {
+        self.calibration.gyro_bias = bias;
+        if let Some(tracker) = self.gyro_bias_tracker.as_mut() {
+            tracker.set_bias(bias);
+        }
+    }
 
-        Ok(gyro)
+    /// Temperature drift model subtracted in `get_acc` and `get_gyro`
+    pub fn get_temp_compensation(&self) -> Option<TempCompensation> {
+        self.calibration.temp
     }
 
-    /// Sensor Temp in degrees celcius
-    pub fn get_temp(&mut self) -> Result<f32, Mpu6050Error<E>> {
-        let mut buf: [u8; 2] = [0; 2];
-        self.read_bytes(TEMP_OUT_H, &mut buf)?;
-        let raw_temp = self.read_word_2c(&buf[0..2]) as f32;
+    /// Set temperature drift model, e.g. from `TempSweep::fit`. `None` disables compensation.
+    /// While enabled, `get_acc` and `get_gyro` additionally read the temperature
+    pub fn set_temp_compensation(&mut self, compensation: Option<TempCompensation>) {
+        self.calibration.temp = compensation;
+    }
 
-        // According to revision 4.2
-        Ok((raw_temp / TEMP_SENSITIVITY) + TEMP_OFFSET)
+    /// Keep refining the gyro bias whenever `get_gyro` finds the sensor at rest. Each `get_gyro` call
+    /// additionally reads the accelerometer to detect rest. `None` disables tracking
+    pub fn set_gyro_bias_tracking(&mut self, tracker: Option<GyroBiasEstimator>) {
+        self.gyro_bias_tracker = tracker.map(|mut tracker| {
+            if !tracker.is_calibrated() && self.calibration.gyro_bias != Vector3::zeros() {
+                tracker.set_bias(self.calibration.gyro_bias);
+            }
+            tracker
+        });
     }
 
-    /// Writes byte to register
+    /// Writes byte to register. With the register cache enabled, writing the cached value is skipped.
+    /// Raw access: writing PWR_MGMT_1 or PWR_MGMT_2 this way bypasses the power state transitions,
+    /// the type of the driver no longer matches the chip then
     pub fn write_byte(&mut self, reg: u8, byte: u8) -> Result<(), Mpu6050Error<E>> {
+        if let Some(cache) = self.register_cache.as_mut() {
+            if cache.get(reg) == Some(byte) {
+                return Ok(());
+            }
+        }
         self.i2c.write(self.slave_addr, &[reg, byte])
             .map_err(Mpu6050Error::I2c)?;
+        if let Some(cache) = self.register_cache.as_mut() {
+            cache.update(reg, byte);
+        }
         // delay disabled for dev build
         // TODO: check effects with physical unit
         // self.delay.delay_ms(10u8);
         Ok(())
     }
 
-    /// Enables bit n at register address reg
+    /// Enables bit n at register address reg. Raw access, see `write_byte`
     pub fn write_bit(&mut self, reg: u8, bit_n: u8, enable: bool) -> Result<(), Mpu6050Error<E>> {
-        let mut byte: [u8; 1] = [0; 1];
-        self.read_bytes(reg, &mut byte)?;
-        bits::set_bit(&mut byte[0], bit_n, enable);
-        Ok(self.write_byte(reg, byte[0])?)
+        let mut byte = self.read_byte(reg)?;
+        bits::set_bit(&mut byte, bit_n, enable);
+        self.write_byte(reg, byte)
     }
 
-    /// Write bits data at reg from start_bit to start_bit+length
+    /// Write bits data at reg from start_bit to start_bit+length. Raw access, see `write_byte`
     pub fn write_bits(&mut self, reg: u8, start_bit: u8, length: u8, data: u8) -> Result<(), Mpu6050Error<E>> {
-        let mut byte: [u8; 1] = [0; 1];
-        self.read_bytes(reg, &mut byte)?;
-        bits::set_bits(&mut byte[0], start_bit, length, data);
-        Ok(self.write_byte(reg, byte[0])?)
+        let mut byte = self.read_byte(reg)?;
+        bits::set_bits(&mut byte, start_bit, length, data);
+        self.write_byte(reg, byte)
     }
 
     /// Read bit n from register
     fn read_bit(&mut self, reg: u8, bit_n: u8) -> Result<u8, Mpu6050Error<E>> {
-        let mut byte: [u8; 1] = [0; 1];
-        self.read_bytes(reg, &mut byte)?;
-        Ok(bits::get_bit(byte[0], bit_n))
+        Ok(bits::get_bit(self.read_byte(reg)?, bit_n))
     }
 
     /// Read bits at register reg, starting with bit start_bit, until start_bit+length
     pub fn read_bits(&mut self, reg: u8, start_bit: u8, length: u8) -> Result<u8, Mpu6050Error<E>> {
-        let mut byte: [u8; 1] = [0; 1];
-        self.read_bytes(reg, &mut byte)?;
-        Ok(bits::get_bits(byte[0], start_bit, length))
+        Ok(bits::get_bits(self.read_byte(reg)?, start_bit, length))
     }
 
-    /// Reads byte from register
+    /// Reads byte from register, from the register cache if enabled and filled
     pub fn read_byte(&mut self, reg: u8) -> Result<u8, Mpu6050Error<E>> {
+        if let Some(byte) = self.register_cache.as_ref().and_then(|cache| cache.get(reg)) {
+            return Ok(byte);
+        }
         let mut byte: [u8; 1] = [0; 1];
         self.i2c.write_read(self.slave_addr, &[reg], &mut byte)
             .map_err(Mpu6050Error::I2c)?;
+        if let Some(cache) = self.register_cache.as_mut() {
+            cache.set(reg, byte[0]);
+        }
         Ok(byte[0])
     }
 
@@ -455,5 +738,448 @@ where
             .map_err(Mpu6050Error::I2c)?;
         Ok(())
     }
+
+    /// Same driver in another power state, after the registers were changed accordingly
+    fn into_mode<NEW>(self) -> Mpu6050<I2C, NEW> {
+        Mpu6050 {
+            i2c: self.i2c,
+            slave_addr: self.slave_addr,
+            acc_sensitivity: self.acc_sensitivity,
+            gyro_sensitivity: self.gyro_sensitivity,
+            calibration: self.calibration,
+            gyro_bias_tracker: self.gyro_bias_tracker,
+            register_cache: self.register_cache,
+            variant: self.variant,
+            magnetometer: self.magnetometer,
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error=E>,
+    MODE: AccelAvailable,
+{
+    /// Roll and pitch estimation from raw accelerometer readings
+    /// NOTE: no yaw! no magnetometer present on MPU6050
+    /// https://www.nxp.com/docs/en/application-note/AN3461.pdf equation 28, 29
+    pub fn get_acc_angles(&mut self) -> Result<Vector2<f32>, Mpu6050Error<E>> {
+        let acc = self.get_acc()?;
+
+        Ok(attitude::acc_angles(&acc))
+    }
+
+    /// Reads rotation (gyro/acc) from specified register
+    fn read_rot(&mut self, reg: u8) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut buf: [u8; 6] = [0; 6];
+        self.read_bytes(reg, &mut buf)?;
+
+        Ok(Vector3::<f32>::new(
+            self.read_word_2c(&buf[0..2]) as f32,
+            self.read_word_2c(&buf[2..4]) as f32,
+            self.read_word_2c(&buf[4..6]) as f32
+        ))
+    }
+
+    /// Accelerometer readings in g, without calibration
+    pub fn get_acc_uncorrected(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut acc = self.read_rot(ACC_REGX_H)?;
+        acc /= self.acc_sensitivity;
+
+        Ok(acc)
+    }
+
+    /// Accelerometer readings in g, calibrated (see `set_accel_calibration`)
+    /// and temperature compensated (see `set_temp_compensation`)
+    pub fn get_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut acc = self.get_acc_uncorrected()?;
+
+        if let Some(comp) = self.calibration.temp {
+            acc -= comp.acc_drift(self.read_temp()?);
+        }
+
+        Ok(self.calibration.acc.apply(&acc))
+    }
+
+    /// Collect one pose for six position accelerometer calibration: reads one window of samples,
+    /// 1 ms apart. Hold the sensor still in `calibrator.next_pose()` and repeat until
+    /// `calibrator.is_complete()`, then apply `calibrator.solve()` with `set_accel_calibration`
+    pub fn calibrate_accel_pose<D: DelayNs>(&mut self, delay: &mut D, calibrator: &mut AccelCalibrator) -> Result<PoseUpdate, Mpu6050Error<E>> {
+        loop {
+            let acc = self.get_acc_uncorrected()?;
+            delay.delay_ms(1u32);
+
+            match calibrator.add_sample(&acc) {
+                PoseUpdate::Collecting => continue,
+                update => return Ok(update),
+            }
+        }
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C, Awake>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Wakes MPU6050 with all sensors enabled (default)
+    fn wake<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
+        // MPU6050 has sleep enabled by default -> set bit 0 to wake
+        // Set clock source to be PLL with x-axis gyroscope reference, bits 2:0 = 001 (See Register Map )
+        self.write_byte(PWR_MGMT_1::ADDR, 0x01)?;
+        delay.delay_ms(100u32);
+        Ok(())
+    }
+
+    /// From Register map:
+    /// "An  internal  8MHz  oscillator,  gyroscope based  clock,or  external  sources  can  be
+    /// selected  as the MPU-60X0 clock source.
+    /// When the internal 8 MHz oscillator or an external source is chosen as the clock source,
+    /// the MPU-60X0 can operate in low power modes with the gyroscopes disabled. Upon power up,
+    /// the MPU-60X0clock source defaults to the internal oscillator. However, it is highly
+    /// recommended  that  the  device beconfigured  to  use  one  of  the  gyroscopes
+    /// (or  an  external  clocksource) as the clock reference for improved stability.
+    /// The clock source can be selected according to the following table...."
+    pub fn set_clock_source(&mut self, source: CLKSEL) -> Result<(), Mpu6050Error<E>> {
+        self.write_clock_source(source)
+    }
+
+    /// enable, disable temperature measurement of sensor
+    /// TEMP_DIS actually saves "disabled status"
+    /// 1 is disabled! -> enable=true : bit=!enable
+    pub fn set_temp_enabled(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
+        self.write_temp_enabled(enable)
+    }
+
+    /// setup motion detection
+    /// sources:
+    /// * https://github.com/kriswiner/MPU6050/blob/a7e0c8ba61a56c5326b2bcd64bc81ab72ee4616b/MPU6050IMU.ino#L486
+    /// * https://arduino.stackexchange.com/a/48430
+    pub fn setup_motion_detection(&mut self) -> Result<(), Mpu6050Error<E>> {
+        self.write_byte(0x6B, 0x00)?;
+        // optional? self.write_byte(0x68, 0x07)?; // Reset all internal signal paths in the MPU-6050 by writing 0x07 to register 0x68;
+        self.write_byte(INT_PIN_CFG::ADDR, 0x20)?; //write register 0x37 to select how to use the interrupt pin. For an active high, push-pull signal that stays until register (decimal) 58 is read, write 0x20.
+        self.write_byte(ACCEL_CONFIG::ADDR, 0x01)?; //Write register 28 (==0x1C) to set the Digital High Pass Filter, bits 3:0. For example set it to 0x01 for 5Hz. (These 3 bits are grey in the data sheet, but they are used! Leaving them 0 means the filter always outputs 0.)
+        self.write_byte(MOT_THR, 10)?; //Write the desired Motion threshold to register 0x1F (For example, write decimal 20).
+        self.write_byte(MOT_DUR, 40)?; //Set motion detect duration to 1  ms; LSB is 1 ms @ 1 kHz rate
+        self.write_byte(0x69, 0x15)?; //to register 0x69, write the motion detection decrement and a few other settings (for example write 0x15 to set both free-fall and motion decrements to 1 and accelerometer start-up delay to 5ms total by adding 1ms. )
+        self.write_byte(INT_ENABLE::ADDR, 0x40)?; //write register 0x38, bit 6 (0x40), to enable motion detection interrupt.
+        Ok(())
+    }
+
+    /// Init wakes MPU6050, identifies the part (see `detect_variant`) and sets default ranges.
+    /// See `Mpu6050Config` to apply a full configuration
+    pub fn init<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
+        self.wake(delay)?;
+        let variant = self.detect_variant()?;
+        self.set_accel_range(AccelRange::G2)?;
+        self.set_gyro_range(GyroRange::D250)?;
+        if variant.has_accel_hpf() {
+            self.set_accel_hpf(ACCEL_HPF::_RESET)?;
+        }
+        Ok(())
+    }
+
+    /// Gyro readings in rad/s, without bias correction
+    pub fn get_gyro_uncorrected(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut gyro = self.read_rot(GYRO_REGX_H)?;
+
+        gyro *= PI_180 / self.gyro_sensitivity;
+
+        Ok(gyro)
+    }
+
+    /// Gyro readings in rad/s, temperature compensated but without bias correction
+    fn get_gyro_temp_compensated(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut gyro = self.get_gyro_uncorrected()?;
+
+        if let Some(comp) = self.calibration.temp {
+            gyro -= comp.gyro_drift(self.get_temp()?);
+        }
+
+        Ok(gyro)
+    }
+
+    /// Gyro readings in rad/s, bias corrected (see `calibrate_gyro`)
+    /// and temperature compensated (see `set_temp_compensation`)
+    pub fn get_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let gyro = self.get_gyro_temp_compensated()?;
+
+        if self.gyro_bias_tracker.is_some() {
+            let acc = self.get_acc()?;
+            if let Some(tracker) = self.gyro_bias_tracker.as_mut() {
+                if tracker.add_sample(&acc, &gyro) == BiasUpdate::Updated {
+                    self.calibration.gyro_bias = tracker.bias();
+                }
+            }
+        }
+
+        Ok(gyro - self.calibration.gyro_bias)
+    }
+
+    /// Estimate gyro bias at startup: averages `samples` readings, 1 ms apart, while the sensor is still.
+    /// Windows with movement are discarded and the measurement is repeated, up to `attempts` times.
+    /// The bias is used by `get_gyro`, fails with `CalibrationNotConverged` if no still window was found
+    pub fn calibrate_gyro<D: DelayNs>(&mut self, delay: &mut D, samples: u16, attempts: u8) -> Result<(), Mpu6050Error<E>> {
+        let mut estimator = GyroBiasEstimator::new(samples);
+
+        for _ in 0..attempts {
+            loop {
+                let acc = self.get_acc()?;
+                let gyro = self.get_gyro_temp_compensated()?;
+                delay.delay_ms(1u32);
+
+                match estimator.add_sample(&acc, &gyro) {
+                    BiasUpdate::Collecting => continue,
+                    BiasUpdate::Rejected => break,
+                    BiasUpdate::Updated => {
+                        self.set_gyro_bias(estimator.bias());
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Err(Mpu6050Error::CalibrationNotConverged { attempts })
+    }
+
+    /// Record one stationary sample for a temperature sweep, see `TempSweep`
+    pub fn record_temp_sample(&mut self, sweep: &mut TempSweep) -> Result<(), Mpu6050Error<E>> {
+        let temp = self.get_temp()?;
+        let acc = self.get_acc_uncorrected()?;
+        let gyro = self.get_gyro_uncorrected()?;
+        sweep.add_sample(temp, &acc, &gyro);
+        Ok(())
+    }
+
+    /// Sensor Temp in degrees celcius
+    pub fn get_temp(&mut self) -> Result<f32, Mpu6050Error<E>> {
+        self.read_temp()
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMpu6050, NoDelay};
+
+    #[test]
+    fn init_test() {
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        assert!(mpu.get_sleep_enabled().unwrap());
+
+        mpu.init(&mut NoDelay).unwrap();
+        assert!(!mpu.get_sleep_enabled().unwrap());
+        assert_eq!(mpu.get_clock_source().unwrap(), CLKSEL::GXAXIS);
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G2);
+        assert_eq!(mpu.get_gyro_range().unwrap(), GyroRange::D250);
+
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x00);
+        let mut mpu = Mpu6050::new(mock);
+        assert!(matches!(mpu.init(&mut NoDelay), Err(Mpu6050Error::InvalidChipId(0x00))));
+    }
+
+    #[test]
+    fn variant_test() {
+        // WHO_AM_I ignores AD0
+        let mut mpu = Mpu6050::new_with_addr(MockMpu6050::new_with_addr(0x69), 0x69);
+        mpu.init(&mut NoDelay).unwrap();
+        assert_eq!(mpu.get_variant(), ChipVariant::Mpu6050);
+
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x70);
+        // MPU6500: ACCEL_CONFIG bits 2:0 are reserved and left alone
+        mock.set_register(ACCEL_CONFIG::ADDR, 0x03);
+        let mut mpu = Mpu6050::new(mock);
+        mpu.init(&mut NoDelay).unwrap();
+        assert_eq!(mpu.get_variant(), ChipVariant::Mpu6500);
+        assert_eq!(mpu.read_byte(ACCEL_CONFIG::ADDR).unwrap(), 0x03);
+        assert_eq!(mpu.get_temp().unwrap(), 21.);
+
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x98);
+        let mut mpu = Mpu6050::new(mock);
+        assert_eq!(mpu.detect_variant().unwrap(), ChipVariant::Counterfeit(0x98));
+        assert!(!mpu.get_variant().is_mpu6500_family());
+    }
+
+    #[test]
+    fn readings_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([4096, -8192, 8192]);
+        mock.set_gyro_raw([131, 0, -262]);
+        mock.set_temp_raw(-340);
+        let mut mpu = Mpu6050::new(mock);
+        mpu.init(&mut NoDelay).unwrap();
+
+        mpu.set_accel_range(AccelRange::G4).unwrap();
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G4);
+        assert_eq!(mpu.get_acc().unwrap(), Vector3::new(0.5, -1., 1.));
+
+        let gyro = mpu.get_gyro().unwrap();
+        assert!((gyro - Vector3::new(PI_180, 0., -2. * PI_180)).amax() < 1e-6);
+
+        assert!((mpu.get_temp().unwrap() - (TEMP_OFFSET - 1.)).abs() < 1e-5);
+
+        mpu.set_gyro_bias(Vector3::new(PI_180, 0., 0.));
+        assert!(mpu.get_gyro().unwrap().x.abs() < 1e-6);
+    }
+
+    #[test]
+    fn motion_detection_test() {
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.setup_motion_detection().unwrap();
+        assert!(!mpu.get_motion_detected().unwrap());
+    }
+
+    #[test]
+    fn register_cache_test() {
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.init(&mut NoDelay).unwrap();
+        assert_eq!(mpu.i2c.transactions(), 8);
+
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.set_register_cache_enabled(true);
+        mpu.init(&mut NoDelay).unwrap();
+        assert_eq!(mpu.i2c.transactions(), 4);
+        // only WHO_AM_I is read again, everything else is unchanged
+        mpu.init(&mut NoDelay).unwrap();
+        assert_eq!(mpu.i2c.transactions(), 5);
+        mpu.set_accel_range(AccelRange::G16).unwrap();
+        assert_eq!(mpu.i2c.transactions(), 6);
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G16);
+        assert_eq!(mpu.i2c.transactions(), 6);
+
+        // chip reset behind the driver's back
+        assert_eq!(mpu.verify_register_cache().unwrap(), 0);
+        mpu.i2c.reset();
+        assert_eq!(mpu.verify_register_cache().unwrap(), 2);
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G2);
+
+        // device reset through the driver drops the cache
+        mpu.set_accel_range(AccelRange::G16).unwrap();
+        mpu.write_byte(PWR_MGMT_1::ADDR, 1 << PWR_MGMT_1::DEVICE_RESET).unwrap();
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G2);
+    }
+
+    /// MPU and a stand-in barometer at 0x77, which answers every read with its chip id
+    struct TwoDevices {
+        mpu: MockMpu6050,
+        barometer_reads: u32,
+    }
+
+    impl embedded_hal::i2c::ErrorType for TwoDevices {
+        type Error = crate::mock::MockError;
+    }
+
+    impl I2c for TwoDevices {
+        fn transaction(&mut self, address: u8, operations: &mut [embedded_hal::i2c::Operation<'_>]) -> Result<(), Self::Error> {
+            if address != 0x77 {
+                return self.mpu.transaction(address, operations);
+            }
+            for operation in operations {
+                if let embedded_hal::i2c::Operation::Read(buf) = operation {
+                    buf.fill(0x58);
+                    self.barometer_reads += 1;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shared_bus_test() {
+        extern crate std;
+        use core::cell::RefCell;
+        use embedded_hal_bus::i2c::{CriticalSectionDevice, MutexDevice, RefCellDevice};
+
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([0, 0, 16384]);
+
+        // borrowed, the bus is free again afterwards
+        let mut mpu = Mpu6050::new_borrowed(&mut mock);
+        mpu.init(&mut NoDelay).unwrap();
+        mock.set_register(MOT_THR, 1);
+
+        let mpu = Mpu6050::new(mock);
+        let mock = mpu.release();
+        assert_eq!(mock.register(MOT_THR), 1);
+
+        // IMU and barometer on one bus
+        let bus = RefCell::new(TwoDevices { mpu: mock, barometer_reads: 0 });
+        let mut mpu = Mpu6050::new(RefCellDevice::new(&bus));
+        let mut barometer = RefCellDevice::new(&bus);
+        assert_eq!(mpu.get_acc().unwrap().z, 1.);
+        let mut id = [0];
+        barometer.write_read(0x77, &[0xd0], &mut id).unwrap();
+        assert_eq!(id, [0x58]);
+        mpu.set_accel_range(AccelRange::G4).unwrap();
+        assert_eq!(mpu.get_acc().unwrap().z, 2.);
+        let devices = bus.into_inner();
+        assert_eq!(devices.barometer_reads, 1);
+        let mock = devices.mpu;
+
+        let bus = critical_section::Mutex::new(RefCell::new(mock));
+        let mut mpu = Mpu6050::new(CriticalSectionDevice::new(&bus));
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G4);
+        let mock = bus.into_inner().into_inner();
+
+        let bus = std::sync::Mutex::new(mock);
+        let mut mpu = Mpu6050::new(MutexDevice::new(&bus));
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G4);
+    }
+
+    #[test]
+    fn error_test() {
+        extern crate std;
+        use crate::mock::MockError;
+        use crate::simulator::{MotionState, SensorErrors, Simulator};
+        use nalgebra::UnitQuaternion;
+        use std::string::ToString;
+
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.init(&mut NoDelay).unwrap();
+
+        // FIFO: partial read, a full FIFO is no overflow until the flag is set
+        mpu.i2c.set_register(FIFO_COUNT_H, 0x00);
+        mpu.i2c.set_register(FIFO_COUNT_H + 1, 12);
+        assert_eq!(mpu.read_fifo(&mut [0; 32]).unwrap(), 12);
+        assert_eq!(mpu.read_fifo(&mut [0; 6]).unwrap(), 6);
+        mpu.i2c.set_register(FIFO_COUNT_H, 0x04);
+        mpu.i2c.set_register(FIFO_COUNT_H + 1, 0x00);
+        assert_eq!(mpu.read_fifo(&mut [0; 32]).unwrap(), 32);
+        mpu.i2c.set_int_status(1 << INT_STATUS::FIFO_OFLOW_INT);
+        assert!(matches!(mpu.read_fifo(&mut [0; 32]), Err(Mpu6050Error::FifoOverflow { count: 1024 })));
+
+        // data ready flag
+        let err = mpu.wait_data_ready(&mut NoDelay, 5).unwrap_err();
+        assert!(matches!(err, Mpu6050Error::DataNotReady { reg: INT_STATUS::ADDR }));
+        assert_eq!(err.to_string(), "data not ready in register 0x3a");
+        mpu.i2c.set_int_status(1 << INT_STATUS::DATA_RDY_INT);
+        mpu.wait_data_ready(&mut NoDelay, 5).unwrap();
+
+        // bus error kinds
+        let nack: Mpu6050Error<MockError> = Mpu6050Error::I2c(MockError::NoAcknowledge);
+        assert_eq!(nack.kind(), Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)));
+        assert_eq!(Mpu6050Error::I2c(MockError::Bus).kind(), Some(ErrorKind::Bus));
+        assert_eq!(Mpu6050Error::<MockError>::AuxNack { address: 0x0c, reg: 0 }.kind(),
+            Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)));
+        assert_eq!(Mpu6050Error::<MockError>::Saturated { reg: 0x09 }.kind(), None);
+        let err: &dyn core::error::Error = &nack;
+        assert_eq!(err.to_string(), "I2C bus error: NoAcknowledge");
+
+        // shaking: no still window for the gyro calibration
+        let shaking = |t: f32| MotionState {
+            acceleration: Vector3::new(5. * libm::sinf(50. * t), 0., 0.),
+            ..MotionState::at_rest(UnitQuaternion::identity())
+        };
+        let mut sim = Simulator::new(shaking, SensorErrors::default());
+        sim.set_auto_advance(Some(0.001));
+        let mut mpu = Mpu6050::new(&mut sim);
+        mpu.init(&mut NoDelay).unwrap();
+        let err = mpu.calibrate_gyro(&mut NoDelay, 50, 2).unwrap_err();
+        assert!(matches!(err, Mpu6050Error::CalibrationNotConverged { attempts: 2 }));
+        assert_eq!(mpu.get_gyro_bias(), Vector3::zeros());
+    }
+}
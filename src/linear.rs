@@ -0,0 +1,55 @@
+//! Splits accelerometer readings into gravity and linear acceleration.
+//!
+//! An accelerometer measures specific force: linear acceleration plus 1 g opposing gravity,
+//! in whatever orientation the board currently has. Subtracting a fixed (0, 0, 1) g only works
+//! while the board is level, so the split needs an orientation estimate, e.g. from `ahrs` or `ekf`.
+//!
+//! All values in g. The world frame is z up, as in `ahrs`.
+
+use crate::ahrs::Orientation;
+use nalgebra::{UnitQuaternion, Vector3};
+
+/// Accelerometer reading split into its components
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AccComponents {
+    /// Gravity part of the reading, sensor frame
+    pub gravity: Vector3<f32>,
+    /// Linear acceleration, sensor frame
+    pub linear: Vector3<f32>,
+    /// Linear acceleration, world frame
+    pub linear_world: Vector3<f32>,
+}
+
+/// Split acc (in g, sensor frame) using orientation q (sensor to world frame)
+pub fn split(acc: &Vector3<f32>, q: &UnitQuaternion<f32>) -> AccComponents {
+    let gravity = q.inverse_transform_vector(&Vector3::z());
+    let linear = acc - gravity;
+
+    AccComponents {
+        gravity,
+        linear,
+        linear_world: q.transform_vector(&linear),
+    }
+}
+
+/// Split acc (in g, sensor frame) using the current estimate of any orientation filter
+pub fn split_with<O: Orientation>(acc: &Vector3<f32>, orientation: &O) -> AccComponents {
+    split(acc, &orientation.quaternion())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_test() {
+        // board rolled by 90°, accelerating upwards with 0.5 g
+        let q = UnitQuaternion::from_euler_angles(crate::PI / 2., 0., 0.);
+        let acc = q.inverse_transform_vector(&Vector3::new(0., 0., 1.5));
+        let parts = split(&acc, &q);
+
+        assert!((parts.gravity - Vector3::new(0., 1., 0.)).amax() < 1e-5);
+        assert!((parts.linear - Vector3::new(0., 0.5, 0.)).amax() < 1e-5);
+        assert!((parts.linear_world - Vector3::new(0., 0., 0.5)).amax() < 1e-5);
+    }
+}
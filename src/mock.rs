@@ -0,0 +1,409 @@
+//! Simulated MPU6050 for host side testing, no hardware needed.
+//!
+//! `MockMpu6050` implements `embedded_hal::i2c::I2c` on top of a full 128 register map:
+//! * reset values from the register map (PWR_MGMT_1 = 0x40, WHO_AM_I = 0x68, everything else 0)
+//! * writes to read only registers (sensor data, status, WHO_AM_I, ...) are ignored
+//! * INT_STATUS and I2C_MST_STATUS are cleared when read
+//! * burst reads and writes auto increment the register pointer
+//! * setting PWR_MGMT_1::DEVICE_RESET restores the reset values
+//! * parts with magnetometer (see `set_who_am_i`) contain an AK8963, reachable at `AK8963_ADDR`
+//!   with bypass enabled, or through I2C slave 0 of the aux master. Aux transfers to other
+//!   addresses flag I2C_SLV0_NACK
+//!
+//! Sensor data is injected with the `set_*` helpers, bus errors with `fail_next`. `NoDelay` stands
+//! in for the delay of `init` and friends. Enabled with the `mock` feature.
+//!
+//! ```
+//! use mpu6050::{Mpu6050, mock::MockMpu6050};
+//!
+//! let mut mock = MockMpu6050::new();
+//! mock.set_acc_raw([0, 0, 16384]);
+//! let mut mpu = Mpu6050::new(mock);
+//! assert_eq!(mpu.get_acc().unwrap().z, 1.);
+//! ```
+
+use crate::ak8963::{self, AK8963_ADDR, AK8963_ID};
+use crate::device::*;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+/// Number of registers in the map
+pub const REGISTER_COUNT: usize = 128;
+
+/// Read only registers per register map, independent of the driver's own list
+fn is_read_only(reg: u8) -> bool {
+    matches!(reg,
+        0x35 // I2C_SLV4_DI
+        | 0x36 // I2C_MST_STATUS
+        | 0x3a..=0x61 // INT_STATUS to MOT_DETECT_STATUS: sensor data, EXT_SENS_DATA
+        | 0x72 | 0x73 // FIFO_COUNT
+        | 0x75 // WHO_AM_I
+    )
+}
+
+/// Errors of the simulated bus
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MockError {
+    /// Transaction addressed to a different slave address
+    NoAcknowledge,
+    /// Injected failure, see `MockMpu6050::fail_next`
+    Bus,
+}
+
+impl embedded_hal::i2c::Error for MockError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            MockError::NoAcknowledge => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            MockError::Bus => ErrorKind::Bus,
+        }
+    }
+}
+
+/// Number of AK8963 registers
+const MAG_REGISTER_COUNT: usize = 0x13;
+
+/// Register map backed AK8963 simulation
+#[derive(Clone, Debug)]
+struct MockAk8963 {
+    registers: [u8; MAG_REGISTER_COUNT],
+    asa: [u8; 3],
+    overflow: bool,
+    pointer: u8,
+}
+
+impl MockAk8963 {
+    fn new() -> Self {
+        let mut registers = [0; MAG_REGISTER_COUNT];
+        registers[ak8963::WIA as usize] = AK8963_ID;
+        MockAk8963 { registers, asa: [128; 3], overflow: false, pointer: 0 }
+    }
+
+    fn read_next(&mut self) -> u8 {
+        let reg = self.pointer as usize % MAG_REGISTER_COUNT;
+        let value = match reg as u8 {
+            ak8963::ST2::ADDR => {
+                // end of a data read
+                self.registers[ak8963::ST1::ADDR as usize] = 0;
+                let bitm = (self.registers[ak8963::CNTL1::ADDR as usize] >> ak8963::CNTL1::BIT) & 1;
+                (bitm << ak8963::ST2::BITM) | ((self.overflow as u8) << ak8963::ST2::HOFL)
+            }
+            r @ ak8963::ASAX..=0x12 => self.asa[(r - ak8963::ASAX) as usize],
+            _ => self.registers[reg],
+        };
+        self.pointer = ((reg + 1) % MAG_REGISTER_COUNT) as u8;
+        value
+    }
+
+    fn write_next(&mut self, value: u8) {
+        let reg = self.pointer as usize % MAG_REGISTER_COUNT;
+        match reg as u8 {
+            ak8963::CNTL1::ADDR => self.registers[reg] = value,
+            ak8963::CNTL2::ADDR if value & (1 << ak8963::CNTL2::SRST) != 0 => {
+                *self = MockAk8963 { asa: self.asa, ..MockAk8963::new() };
+            }
+            _ => {}
+        }
+        self.pointer = ((reg + 1) % MAG_REGISTER_COUNT) as u8;
+    }
+
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) {
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    if let Some((reg, data)) = bytes.split_first() {
+                        self.pointer = *reg;
+                        for byte in data {
+                            self.write_next(*byte);
+                        }
+                    }
+                }
+                Operation::Read(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = self.read_next();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Register map backed MPU6050 simulation
+#[derive(Clone, Debug)]
+pub struct MockMpu6050 {
+    address: u8,
+    registers: [u8; REGISTER_COUNT],
+    reset_values: [u8; REGISTER_COUNT],
+    pointer: u8,
+    transactions: u32,
+    fail: u32,
+    mag: MockAk8963,
+}
+
+impl Default for MockMpu6050 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockMpu6050 {
+    /// Device at `DEFAULT_SLAVE_ADDR` in reset state
+    pub fn new() -> Self {
+        Self::new_with_addr(DEFAULT_SLAVE_ADDR)
+    }
+
+    /// Device at a custom address in reset state. WHO_AM_I still reads 0x68, like the real chip
+    pub fn new_with_addr(address: u8) -> Self {
+        let mut reset_values = [0; REGISTER_COUNT];
+        reset_values[PWR_MGMT_1::ADDR as usize] = 0x40;
+        reset_values[WHOAMI as usize] = DEFAULT_SLAVE_ADDR;
+
+        MockMpu6050 {
+            address,
+            registers: reset_values,
+            reset_values,
+            pointer: 0,
+            transactions: 0,
+            fail: 0,
+            mag: MockAk8963::new(),
+        }
+    }
+
+    /// Restore reset values
+    pub fn reset(&mut self) {
+        self.registers = self.reset_values;
+    }
+
+    /// Raw register value, without read side effects
+    pub fn register(&self, reg: u8) -> u8 {
+        self.registers[reg as usize % REGISTER_COUNT]
+    }
+
+    /// Set any register, including read only ones, without write side effects
+    pub fn set_register(&mut self, reg: u8, value: u8) {
+        self.registers[reg as usize % REGISTER_COUNT] = value;
+    }
+
+    /// Change the value WHO_AM_I reads, also after reset, e.g. to simulate other chips
+    pub fn set_who_am_i(&mut self, value: u8) {
+        self.reset_values[WHOAMI as usize] = value;
+        self.registers[WHOAMI as usize] = value;
+    }
+
+    /// Set raw accelerometer output, x y z
+    pub fn set_acc_raw(&mut self, acc: [i16; 3]) {
+        self.set_words(ACC_REGX_H, &acc);
+    }
+
+    /// Set raw gyro output, x y z
+    pub fn set_gyro_raw(&mut self, gyro: [i16; 3]) {
+        self.set_words(GYRO_REGX_H, &gyro);
+    }
+
+    /// Set raw temperature output
+    pub fn set_temp_raw(&mut self, temp: i16) {
+        self.set_words(TEMP_OUT_H, &[temp]);
+    }
+
+    /// Set INT_STATUS bits, they clear on the next read
+    pub fn set_int_status(&mut self, status: u8) {
+        self.registers[INT_STATUS::ADDR as usize] = status;
+    }
+
+    /// Set raw AK8963 output in its own axes, x y z, and flag data ready. Flags data overrun
+    /// as well if the previous output wasn't read
+    pub fn set_mag_raw(&mut self, mag: [i16; 3]) {
+        let st1 = &mut self.mag.registers[ak8963::ST1::ADDR as usize];
+        let overrun = *st1 & (1 << ak8963::ST1::DRDY) != 0;
+        *st1 = (1 << ak8963::ST1::DRDY) | ((overrun as u8) << ak8963::ST1::DOR);
+        for (i, value) in mag.iter().enumerate() {
+            let [low, high] = value.to_le_bytes();
+            self.mag.registers[ak8963::HXL as usize + 2 * i] = low;
+            self.mag.registers[ak8963::HXL as usize + 2 * i + 1] = high;
+        }
+    }
+
+    /// Set the AK8963 sensitivity adjustment values, 128 is no adjustment
+    pub fn set_mag_asa(&mut self, asa: [u8; 3]) {
+        self.mag.asa = asa;
+    }
+
+    /// Set the AK8963 overflow flag
+    pub fn set_mag_overflow(&mut self, overflow: bool) {
+        self.mag.overflow = overflow;
+    }
+
+    /// Raw AK8963 register value
+    pub fn mag_register(&self, reg: u8) -> u8 {
+        self.mag.registers[reg as usize % MAG_REGISTER_COUNT]
+    }
+
+    /// Number of bus transactions addressed to this device so far
+    pub fn transactions(&self) -> u32 {
+        self.transactions
+    }
+
+    /// Let the next n transactions fail with `MockError::Bus`
+    pub fn fail_next(&mut self, n: u32) {
+        self.fail = n;
+    }
+
+    fn set_words(&mut self, reg: u8, words: &[i16]) {
+        for (i, word) in words.iter().enumerate() {
+            let [high, low] = word.to_be_bytes();
+            self.set_register(reg + 2 * i as u8, high);
+            self.set_register(reg + 2 * i as u8 + 1, low);
+        }
+    }
+
+    fn has_magnetometer(&self) -> bool {
+        ChipVariant::from_who_am_i(self.register(WHOAMI)).is_some_and(|v| v.has_magnetometer())
+    }
+
+    fn bit(&self, reg: u8, bit: u8) -> bool {
+        self.register(reg) & (1 << bit) != 0
+    }
+
+    /// Aux master slave 0 transfer, done right away instead of with the next sample
+    fn aux_transfer(&mut self) {
+        let addr = self.register(I2C_SLV0::ADDR);
+        if !self.bit(USER_CTRL::ADDR, USER_CTRL::I2C_MST_EN) || !self.bit(I2C_SLV0::CTRL, I2C_SLV0::EN) {
+            return;
+        }
+        if !self.has_magnetometer() || addr & 0x7f != AK8963_ADDR {
+            self.registers[I2C_MST_STATUS::ADDR as usize] |= 1 << I2C_MST_STATUS::I2C_SLV0_NACK;
+            return;
+        }
+        self.mag.pointer = self.register(I2C_SLV0::REG);
+        if addr & (1 << I2C_SLV0::RNW) != 0 {
+            let len = (self.register(I2C_SLV0::CTRL) & 0x0f) as usize;
+            for i in 0..len {
+                self.registers[EXT_SENS_DATA_00 as usize + i] = self.mag.read_next();
+            }
+        } else {
+            let value = self.register(I2C_SLV0::DO);
+            self.mag.write_next(value);
+        }
+    }
+
+    fn read_next(&mut self) -> u8 {
+        let reg = self.pointer;
+        if reg == EXT_SENS_DATA_00 {
+            self.aux_transfer();
+        }
+        let value = self.register(reg);
+        if reg == INT_STATUS::ADDR || reg == I2C_MST_STATUS::ADDR {
+            self.registers[reg as usize] = 0;
+        }
+        self.pointer = (reg + 1) % REGISTER_COUNT as u8;
+        value
+    }
+
+    fn write_next(&mut self, value: u8) {
+        let reg = self.pointer;
+        if reg == PWR_MGMT_1::ADDR && value & (1 << PWR_MGMT_1::DEVICE_RESET) != 0 {
+            self.reset();
+        } else if !is_read_only(reg) {
+            self.registers[reg as usize] = value;
+            if reg == I2C_SLV0::CTRL {
+                self.aux_transfer();
+            }
+        }
+        self.pointer = (reg + 1) % REGISTER_COUNT as u8;
+    }
+}
+
+impl ErrorType for MockMpu6050 {
+    type Error = MockError;
+}
+
+impl I2c for MockMpu6050 {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        if address == AK8963_ADDR && self.has_magnetometer() && self.bit(INT_PIN_CFG::ADDR, INT_PIN_CFG::I2C_BYPASS_EN) {
+            self.mag.transaction(operations);
+            return Ok(());
+        }
+        if address != self.address {
+            return Err(MockError::NoAcknowledge);
+        }
+        self.transactions += 1;
+        if self.fail > 0 {
+            self.fail -= 1;
+            return Err(MockError::Bus);
+        }
+
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    if let Some((reg, data)) = bytes.split_first() {
+                        self.pointer = reg % REGISTER_COUNT as u8;
+                        for byte in data {
+                            self.write_next(*byte);
+                        }
+                    }
+                }
+                Operation::Read(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = self.read_next();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Delay that returns immediately, for driver calls in tests
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_map_test() {
+        let mut mock = MockMpu6050::new();
+        let mut buf = [0u8; 2];
+
+        // reset values
+        mock.write_read(DEFAULT_SLAVE_ADDR, &[PWR_MGMT_1::ADDR], &mut buf).unwrap();
+        assert_eq!(buf, [0x40, 0x00]);
+        mock.write_read(DEFAULT_SLAVE_ADDR, &[WHOAMI], &mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 0x68);
+
+        // read only and burst write
+        mock.write(DEFAULT_SLAVE_ADDR, &[GYRO_CONFIG::ADDR, 0x18, 0x08]).unwrap();
+        mock.write(DEFAULT_SLAVE_ADDR, &[WHOAMI, 0x00]).unwrap();
+        assert_eq!(mock.register(GYRO_CONFIG::ADDR), 0x18);
+        assert_eq!(mock.register(ACCEL_CONFIG::ADDR), 0x08);
+        assert_eq!(mock.register(WHOAMI), 0x68);
+        for reg in [0x35, 0x36, ACC_REGX_H, 0x61, 0x72, 0x73] {
+            mock.write(DEFAULT_SLAVE_ADDR, &[reg, 0x5a]).unwrap();
+            assert_eq!(mock.register(reg), 0, "0x{:02x}", reg);
+        }
+        mock.write(DEFAULT_SLAVE_ADDR, &[0x34, 0x5a]).unwrap();
+        assert_eq!(mock.register(0x34), 0x5a);
+
+        // clear on read
+        mock.set_int_status(0x41);
+        mock.write_read(DEFAULT_SLAVE_ADDR, &[INT_STATUS::ADDR], &mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 0x41);
+        mock.write_read(DEFAULT_SLAVE_ADDR, &[INT_STATUS::ADDR], &mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 0x00);
+
+        // device reset
+        mock.write(DEFAULT_SLAVE_ADDR, &[PWR_MGMT_1::ADDR, 0x80]).unwrap();
+        assert_eq!(mock.register(GYRO_CONFIG::ADDR), 0x00);
+        assert_eq!(mock.register(PWR_MGMT_1::ADDR), 0x40);
+
+        // wrong address and injected failures
+        assert_eq!(mock.write(0x69, &[0x00, 0x00]), Err(MockError::NoAcknowledge));
+        mock.fail_next(1);
+        assert_eq!(mock.write(DEFAULT_SLAVE_ADDR, &[0x00, 0x00]), Err(MockError::Bus));
+        assert!(mock.write(DEFAULT_SLAVE_ADDR, &[0x00, 0x00]).is_ok());
+    }
+}
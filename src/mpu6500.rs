@@ -0,0 +1,510 @@
+//! Register extensions of the MPU6500 family (MPU6500, MPU9250, MPU9255, ICM-20602).
+//!
+//! The shared API of `Mpu6050` works on all variants. The functions here need a part found by
+//! `detect_variant` (`init` runs it) and fail with `Mpu6050Error::Unsupported` on the MPU6050:
+//! the addresses are used by other registers there.
+//!
+//! * separate accelerometer DLPF in ACCEL_CONFIG2
+//! * wake on motion with a threshold in mg
+//! * self test against the factory trim in SELF_TEST_X/Y/Z_GYRO and _ACCEL
+//! * FIFO stop when full. The FIFO is 512 bytes, see `ChipVariant::fifo_size`
+//!
+//! The ICM-20602 moves the gyro factory trim to XG_ST_DATA and has a threshold per axis for
+//! wake on motion. It has no LPOSC_CLKSEL: `set_accel_odr`, and with it `into_wake_on_motion`,
+//! fail with `Unsupported` there.
+//!
+//! ```no_run
+//! use mpu6050::{Mpu6050, mpu6500::*};
+//! use linux_embedded_hal::{I2cdev, Delay};
+//!
+//! let mut mpu = Mpu6050::new(I2cdev::new("/dev/i2c-1").unwrap());
+//! mpu.init(&mut Delay).unwrap();
+//! mpu.set_accel_dlpf(A_DLPF_CFG::_41).unwrap();
+//! mpu.self_test(&mut Delay).unwrap();
+//!
+//! let mut mpu = mpu.into_wake_on_motion(100, LPOSC_CLKSEL::_15P63).unwrap();
+//! while !mpu.get_motion_detected().unwrap() {}
+//! ```
+
+// transitions hand back the driver on errors, see `power`
+#![allow(clippy::result_large_err)]
+
+use crate::device::*;
+use crate::power::{Awake, LowPowerAccel, Transition};
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+use nalgebra::Vector3;
+
+/// Register 0-2: Gyro self test factory trim, X, Y, Z
+pub const SELF_TEST_GYRO: u8 = 0x00;
+/// Register 13-15: Accel self test factory trim, X, Y, Z
+pub const SELF_TEST_ACCEL: u8 = 0x0d;
+/// Register 31: Wake on motion threshold, 4 mg/LSB
+pub const WOM_THR: u8 = 0x1f;
+/// ICM-20602 register 80-82: Gyro self test factory trim, X, Y, Z
+pub const XG_ST_DATA: u8 = 0x50;
+/// ICM-20602 register 32-34: Wake on motion threshold, X, Y, Z, 4 mg/LSB
+pub const ACCEL_WOM_THR: u8 = 0x20;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 29: Accel Config 2
+pub struct ACCEL_CONFIG2;
+
+impl ACCEL_CONFIG2 {
+    /// Base Address
+    pub const ADDR: u8 = 0x1d;
+    /// Bypass the accel DLPF
+    pub const ACCEL_FCHOICE_B: u8 = 3;
+    /// Accel DLPF config, including ACCEL_FCHOICE_B
+    pub const A_DLPF_CFG: BitBlock = BitBlock { bit: 3, length: 4 };
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 30: Low Power Accel Output Data Rate
+pub struct LP_ACCEL_ODR;
+
+impl LP_ACCEL_ODR {
+    /// Base Address
+    pub const ADDR: u8 = 0x1e;
+    /// Wake up frequency in cycle mode
+    pub const LPOSC_CLKSEL: BitBlock = BitBlock { bit: 3, length: 4 };
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 105: Accel Intelligence Control
+pub struct ACCEL_INTEL_CTRL;
+
+impl ACCEL_INTEL_CTRL {
+    /// Base Address
+    pub const ADDR: u8 = 0x69;
+    /// Enable wake on motion logic
+    pub const ACCEL_INTEL_EN: u8 = 7;
+    /// Compare the current sample to the previous one
+    pub const ACCEL_INTEL_MODE: u8 = 6;
+}
+
+/// Register 26, Configuration: stop writing to a full FIFO instead of overwriting the oldest data
+pub const FIFO_MODE: u8 = 6;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// Accelerometer Digital Low Pass Filter bandwidth
+pub enum A_DLPF_CFG {
+    /// 460 Hz
+    _460 = 0,
+    /// 184 Hz
+    _184,
+    /// 92 Hz
+    _92,
+    /// 41 Hz
+    _41,
+    /// 20 Hz
+    _20,
+    /// 10 Hz
+    _10,
+    /// 5 Hz
+    _5,
+    /// DLPF bypassed (ACCEL_FCHOICE_B), 1.13 kHz at 4 kHz output rate
+    _1130 = 8,
+}
+
+impl From<u8> for A_DLPF_CFG {
+    fn from(cfg: u8) -> Self {
+        match cfg {
+            1 => A_DLPF_CFG::_184,
+            2 => A_DLPF_CFG::_92,
+            3 => A_DLPF_CFG::_41,
+            4 => A_DLPF_CFG::_20,
+            5 => A_DLPF_CFG::_10,
+            6 => A_DLPF_CFG::_5,
+            8..=15 => A_DLPF_CFG::_1130,
+            _ => A_DLPF_CFG::_460,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// Wake up frequency in cycle mode, replaces `LP_WAKE_CTRL`
+pub enum LPOSC_CLKSEL {
+    /// 0.24 Hz
+    _0P24 = 0,
+    /// 0.49 Hz
+    _0P49,
+    /// 0.98 Hz
+    _0P98,
+    /// 1.95 Hz
+    _1P95,
+    /// 3.91 Hz
+    _3P91,
+    /// 7.81 Hz
+    _7P81,
+    /// 15.63 Hz
+    _15P63,
+    /// 31.25 Hz
+    _31P25,
+    /// 62.5 Hz
+    _62P5,
+    /// 125 Hz
+    _125,
+    /// 250 Hz
+    _250,
+    /// 500 Hz
+    _500,
+}
+
+impl From<u8> for LPOSC_CLKSEL {
+    fn from(freq: u8) -> Self {
+        match freq {
+            0 => LPOSC_CLKSEL::_0P24,
+            1 => LPOSC_CLKSEL::_0P49,
+            2 => LPOSC_CLKSEL::_0P98,
+            3 => LPOSC_CLKSEL::_1P95,
+            4 => LPOSC_CLKSEL::_3P91,
+            5 => LPOSC_CLKSEL::_7P81,
+            6 => LPOSC_CLKSEL::_15P63,
+            7 => LPOSC_CLKSEL::_31P25,
+            8 => LPOSC_CLKSEL::_62P5,
+            9 => LPOSC_CLKSEL::_125,
+            10 => LPOSC_CLKSEL::_250,
+            _ => LPOSC_CLKSEL::_500,
+        }
+    }
+}
+
+/// Samples averaged with and without self test excitation
+const SELF_TEST_SAMPLES: u16 = 200;
+
+/// Self test response and factory trim, both at 250 °/s and 2 g
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SelfTest {
+    /// Gyro output change with self test enabled, LSB
+    pub gyro_response: Vector3<f32>,
+    /// Accel output change with self test enabled, LSB
+    pub accel_response: Vector3<f32>,
+    /// Gyro factory trim codes, 0 if not programmed
+    pub gyro_trim: [u8; 3],
+    /// Accel factory trim codes, 0 if not programmed
+    pub accel_trim: [u8; 3],
+}
+
+impl SelfTest {
+    /// Factory self test response in LSB from a trim code
+    pub fn factory_response(code: u8) -> f32 {
+        if code == 0 {
+            0.
+        } else {
+            2620. * libm::powf(1.01, code as f32 - 1.)
+        }
+    }
+
+    /// Gyro response relative to the factory response, 0 for untrimmed axes
+    pub fn gyro_ratio(&self) -> Vector3<f32> {
+        Self::ratio(&self.gyro_response, &self.gyro_trim)
+    }
+
+    /// Accel response relative to the factory response, 0 for untrimmed axes
+    pub fn accel_ratio(&self) -> Vector3<f32> {
+        Self::ratio(&self.accel_response, &self.accel_trim)
+    }
+
+    /// Gyro passes with more than half the factory response, or 60 °/s without trim
+    pub fn gyro_passed(&self) -> bool {
+        (0..3).all(|i| match self.gyro_trim[i] {
+            0 => libm::fabsf(self.gyro_response[i]) >= 60. * GYRO_SENS.0,
+            _ => self.gyro_ratio()[i] > 0.5,
+        })
+    }
+
+    /// Accel passes within 50% to 150% of the factory response, or 225 to 675 mg without trim
+    pub fn accel_passed(&self) -> bool {
+        (0..3).all(|i| match self.accel_trim[i] {
+            0 => (0.225 * ACCEL_SENS.0..=0.675 * ACCEL_SENS.0).contains(&libm::fabsf(self.accel_response[i])),
+            _ => (0.5..=1.5).contains(&self.accel_ratio()[i]),
+        })
+    }
+
+    /// Both gyro and accel passed
+    pub fn passed(&self) -> bool {
+        self.gyro_passed() && self.accel_passed()
+    }
+
+    fn ratio(response: &Vector3<f32>, trim: &[u8; 3]) -> Vector3<f32> {
+        Vector3::from_fn(|i, _| match trim[i] {
+            0 => 0.,
+            code => response[i] / Self::factory_response(code),
+        })
+    }
+}
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Fails with `Unsupported` unless the detected part is in the MPU6500 family
+    fn require_mpu6500(&self) -> Result<(), Mpu6050Error<E>> {
+        if self.variant.is_mpu6500_family() {
+            Ok(())
+        } else {
+            Err(Mpu6050Error::Unsupported(self.variant))
+        }
+    }
+
+    /// Fails with `Unsupported` on the ICM-20602, where register 30 is LP_MODE_CFG
+    fn require_lposc(&self) -> Result<(), Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        match self.variant {
+            ChipVariant::Icm20602 => Err(Mpu6050Error::Unsupported(self.variant)),
+            _ => Ok(()),
+        }
+    }
+
+    /// set accelerometer low pass filter, independent of the gyro DLPF (`set_dlpf`)
+    pub fn set_accel_dlpf(&mut self, cfg: A_DLPF_CFG) -> Result<(), Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        self.write_bits(ACCEL_CONFIG2::ADDR, ACCEL_CONFIG2::A_DLPF_CFG.bit, ACCEL_CONFIG2::A_DLPF_CFG.length, cfg as u8)
+    }
+
+    /// get accelerometer low pass filter
+    pub fn get_accel_dlpf(&mut self) -> Result<A_DLPF_CFG, Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        Ok(A_DLPF_CFG::from(self.read_bits(ACCEL_CONFIG2::ADDR, ACCEL_CONFIG2::A_DLPF_CFG.bit, ACCEL_CONFIG2::A_DLPF_CFG.length)?))
+    }
+
+    /// set wake on motion threshold in mg, 4 mg resolution, up to 1020 mg. Sets all axes on the ICM-20602
+    pub fn set_wom_threshold(&mut self, mg: u16) -> Result<(), Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        let threshold = (mg / 4).min(255) as u8;
+        match self.variant {
+            ChipVariant::Icm20602 => (ACCEL_WOM_THR..ACCEL_WOM_THR + 3).try_for_each(|reg| self.write_byte(reg, threshold)),
+            _ => self.write_byte(WOM_THR, threshold),
+        }
+    }
+
+    /// get wake on motion threshold in mg, of the X axis on the ICM-20602
+    pub fn get_wom_threshold(&mut self) -> Result<u16, Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        let reg = match self.variant {
+            ChipVariant::Icm20602 => ACCEL_WOM_THR,
+            _ => WOM_THR,
+        };
+        Ok(self.read_byte(reg)? as u16 * 4)
+    }
+
+    /// set wake up frequency in cycle mode, used instead of `set_wake_frequency`
+    pub fn set_accel_odr(&mut self, freq: LPOSC_CLKSEL) -> Result<(), Mpu6050Error<E>> {
+        self.require_lposc()?;
+        self.write_bits(LP_ACCEL_ODR::ADDR, LP_ACCEL_ODR::LPOSC_CLKSEL.bit, LP_ACCEL_ODR::LPOSC_CLKSEL.length, freq as u8)
+    }
+
+    /// get wake up frequency in cycle mode
+    pub fn get_accel_odr(&mut self) -> Result<LPOSC_CLKSEL, Mpu6050Error<E>> {
+        self.require_lposc()?;
+        Ok(LPOSC_CLKSEL::from(self.read_bits(LP_ACCEL_ODR::ADDR, LP_ACCEL_ODR::LPOSC_CLKSEL.bit, LP_ACCEL_ODR::LPOSC_CLKSEL.length)?))
+    }
+
+    /// enable, disable FIFO stop when full. Disabled, the oldest data is overwritten
+    pub fn set_fifo_stop_when_full(&mut self, enable: bool) -> Result<(), Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        self.write_bit(CONFIG::ADDR, FIFO_MODE, enable)
+    }
+
+    /// get FIFO stop when full
+    pub fn get_fifo_stop_when_full(&mut self) -> Result<bool, Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        Ok(self.read_bit(CONFIG::ADDR, FIFO_MODE)? != 0)
+    }
+
+    /// Factory trim codes of the self test: gyro X, Y, Z and accel X, Y, Z
+    pub fn get_self_test_trim(&mut self) -> Result<([u8; 3], [u8; 3]), Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        let mut gyro = [0; 3];
+        let mut accel = [0; 3];
+        let gyro_reg = match self.variant {
+            ChipVariant::Icm20602 => XG_ST_DATA,
+            _ => SELF_TEST_GYRO,
+        };
+        self.read_bytes(gyro_reg, &mut gyro)?;
+        self.read_bytes(SELF_TEST_ACCEL, &mut accel)?;
+        Ok((gyro, accel))
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C, Awake>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Run the self test: average 200 samples at 1 kHz, 92 Hz DLPF, 250 °/s and 2 g with and without
+    /// self test excitation, and compare the difference to the factory trim. Takes about half a second,
+    /// hold the sensor still. Sample rate, filters and ranges are restored afterwards
+    pub fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<SelfTest, Mpu6050Error<E>> {
+        self.require_mpu6500()?;
+        let (gyro_trim, accel_trim) = self.get_self_test_trim()?;
+
+        let saved = [SMPLRT_DIV, CONFIG::ADDR, GYRO_CONFIG::ADDR, ACCEL_CONFIG::ADDR, ACCEL_CONFIG2::ADDR];
+        let mut values = [0; 5];
+        for (value, reg) in values.iter_mut().zip(saved.iter()) {
+            *value = self.read_byte(*reg)?;
+        }
+
+        let result = self.self_test_run(delay).map(|(gyro_response, accel_response)| SelfTest {
+            gyro_response,
+            accel_response,
+            gyro_trim,
+            accel_trim,
+        });
+
+        for (value, reg) in values.iter().zip(saved.iter()) {
+            self.write_byte(*reg, *value)?;
+        }
+        match result? {
+            result if result.passed() => Ok(result),
+            result => Err(Mpu6050Error::SelfTestFailed(result)),
+        }
+    }
+
+    /// Gyro and accel response to the self test excitation, LSB
+    fn self_test_run<D: DelayNs>(&mut self, delay: &mut D) -> Result<(Vector3<f32>, Vector3<f32>), Mpu6050Error<E>> {
+        self.write_byte(SMPLRT_DIV, 0)?;
+        self.write_byte(CONFIG::ADDR, DLPF_CFG::_94 as u8)?;
+        self.write_byte(ACCEL_CONFIG2::ADDR, A_DLPF_CFG::_92 as u8)?;
+        // 250 °/s, 2 g
+        self.write_byte(GYRO_CONFIG::ADDR, 0)?;
+        self.write_byte(ACCEL_CONFIG::ADDR, 0)?;
+        let (gyro, accel) = self.self_test_average(delay)?;
+
+        let all_axes = (1 << GYRO_CONFIG::XG_ST) | (1 << GYRO_CONFIG::YG_ST) | (1 << GYRO_CONFIG::ZG_ST);
+        self.write_byte(GYRO_CONFIG::ADDR, all_axes)?;
+        self.write_byte(ACCEL_CONFIG::ADDR, all_axes)?;
+        delay.delay_ms(20u32);
+        let (gyro_st, accel_st) = self.self_test_average(delay)?;
+
+        self.write_byte(GYRO_CONFIG::ADDR, 0)?;
+        self.write_byte(ACCEL_CONFIG::ADDR, 0)?;
+        delay.delay_ms(20u32);
+        Ok((gyro_st - gyro, accel_st - accel))
+    }
+
+    /// Raw gyro and accel average, 1 ms apart
+    fn self_test_average<D: DelayNs>(&mut self, delay: &mut D) -> Result<(Vector3<f32>, Vector3<f32>), Mpu6050Error<E>> {
+        let mut gyro = Vector3::zeros();
+        let mut accel = Vector3::zeros();
+        for _ in 0..SELF_TEST_SAMPLES {
+            gyro += self.read_rot(GYRO_REGX_H)?;
+            accel += self.read_rot(ACC_REGX_H)?;
+            delay.delay_ms(1u32);
+        }
+        Ok((gyro / SELF_TEST_SAMPLES as f32, accel / SELF_TEST_SAMPLES as f32))
+    }
+
+    /// Enter cycle mode with wake on motion: gyros off, accel sampled at `freq` and compared to the
+    /// previous sample. Motion above `threshold_mg` on any axis raises the motion interrupt, see
+    /// `get_motion_detected`
+    pub fn into_wake_on_motion(self, threshold_mg: u16, freq: LPOSC_CLKSEL) -> Transition<I2C, Awake, LowPowerAccel, E> {
+        self.transition(|mpu| {
+            mpu.require_lposc()?;
+            mpu.set_sleep_enabled(false)?;
+            mpu.set_cycle_enabled(false)?;
+            mpu.set_gyro_standby(true)?;
+            mpu.set_accel_dlpf(A_DLPF_CFG::_184)?;
+            mpu.write_byte(INT_ENABLE::ADDR, 1 << INT_ENABLE::MOT_EN)?;
+            mpu.write_byte(ACCEL_INTEL_CTRL::ADDR, (1 << ACCEL_INTEL_CTRL::ACCEL_INTEL_EN) | (1 << ACCEL_INTEL_CTRL::ACCEL_INTEL_MODE))?;
+            mpu.set_wom_threshold(threshold_mg)?;
+            mpu.set_accel_odr(freq)?;
+            mpu.set_cycle_enabled(true)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMpu6050, NoDelay};
+
+    #[test]
+    fn mpu6500_test() {
+        // not on the MPU6050, ACCEL_CONFIG2 is FF_THR there
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.init(&mut NoDelay).unwrap();
+        assert!(matches!(mpu.set_accel_dlpf(A_DLPF_CFG::_41), Err(Mpu6050Error::Unsupported(ChipVariant::Mpu6050))));
+
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x71);
+        mock.set_gyro_raw([131, 0, 0]);
+        mock.set_acc_raw([0, 0, 16384]);
+        mock.set_register(SELF_TEST_GYRO, 100);
+        let mut mpu = Mpu6050::new(mock);
+        mpu.init(&mut NoDelay).unwrap();
+
+        mpu.set_accel_dlpf(A_DLPF_CFG::_1130).unwrap();
+        assert_eq!(mpu.read_byte(ACCEL_CONFIG2::ADDR).unwrap(), 0x08);
+        assert_eq!(mpu.get_accel_dlpf().unwrap(), A_DLPF_CFG::_1130);
+        mpu.set_wom_threshold(2000).unwrap();
+        assert_eq!(mpu.get_wom_threshold().unwrap(), 1020);
+        mpu.set_fifo_stop_when_full(true).unwrap();
+        assert!(mpu.get_fifo_stop_when_full().unwrap());
+
+        // the mock doesn't respond to the excitation: no response, settings restored
+        mpu.set_accel_range(AccelRange::G8).unwrap();
+        let result = match mpu.self_test(&mut NoDelay) {
+            Err(Mpu6050Error::SelfTestFailed(result)) => result,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(result.gyro_trim, [100, 0, 0]);
+        assert_eq!(result.gyro_response, Vector3::zeros());
+        assert!(!result.passed());
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G8);
+        assert_eq!(mpu.get_accel_dlpf().unwrap(), A_DLPF_CFG::_1130);
+        assert!(mpu.get_fifo_stop_when_full().unwrap());
+
+        let passing = SelfTest {
+            gyro_response: Vector3::new(SelfTest::factory_response(100), 8000., 8000.),
+            accel_response: Vector3::new(0., 0., 8000.),
+            gyro_trim: [100, 0, 0],
+            accel_trim: [0, 0, 0],
+        };
+        assert_eq!(passing.gyro_ratio(), Vector3::new(1., 0., 0.));
+        assert!(passing.gyro_passed() && !passing.accel_passed());
+
+        let mut mpu = mpu.into_wake_on_motion(40, LPOSC_CLKSEL::_31P25).unwrap();
+        assert_eq!(mpu.get_wom_threshold().unwrap(), 40);
+        assert_eq!(mpu.get_accel_odr().unwrap(), LPOSC_CLKSEL::_31P25);
+        assert_eq!(mpu.read_byte(ACCEL_INTEL_CTRL::ADDR).unwrap(), 0xc0);
+        assert_eq!(mpu.read_byte(INT_ENABLE::ADDR).unwrap(), 0x40);
+        assert!(mpu.get_cycle_enabled().unwrap() && mpu.get_gyro_standby().unwrap());
+    }
+
+    #[test]
+    fn icm20602_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x12);
+        mock.set_register(SELF_TEST_GYRO, 7);
+        mock.set_register(XG_ST_DATA, 100);
+        mock.set_register(SELF_TEST_ACCEL, 50);
+        let mut mpu = Mpu6050::new(mock);
+        mpu.init(&mut NoDelay).unwrap();
+        assert_eq!(mpu.get_variant(), ChipVariant::Icm20602);
+
+        // threshold per axis, 0x1f is reserved
+        mpu.set_wom_threshold(40).unwrap();
+        assert_eq!(mpu.read_byte(WOM_THR).unwrap(), 0);
+        let mut thresholds = [0; 3];
+        mpu.read_bytes(ACCEL_WOM_THR, &mut thresholds).unwrap();
+        assert_eq!(thresholds, [10; 3]);
+        assert_eq!(mpu.get_wom_threshold().unwrap(), 40);
+
+        assert_eq!(mpu.get_self_test_trim().unwrap(), ([100, 0, 0], [50, 0, 0]));
+
+        // 0x1e is LP_MODE_CFG, left untouched
+        assert!(matches!(mpu.set_accel_odr(LPOSC_CLKSEL::_31P25), Err(Mpu6050Error::Unsupported(ChipVariant::Icm20602))));
+        assert_eq!(mpu.read_byte(LP_ACCEL_ODR::ADDR).unwrap(), 0);
+        match mpu.into_wake_on_motion(40, LPOSC_CLKSEL::_31P25) {
+            Err(failed) => assert!(matches!(failed.error, Mpu6050Error::Unsupported(ChipVariant::Icm20602))),
+            Ok(_) => panic!("wake on motion on the ICM-20602"),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Power states in the type of the driver, so reads that would return stale data don't compile.
+//!
+//! * `Awake`: all sensors running, the default of `Mpu6050::new`. As that constructor has no side
+//!   effects, the type is only true once `init` ran; `new_asleep` types a fresh chip correctly
+//! * `Asleep`: everything off, only configuration is available
+//! * `LowPowerAccel`: cycle mode, gyros and temperature sensor off, only accelerometer reads
+//!
+//! Transitions consume the driver and return it in the new state. On a bus error the driver is
+//! handed back in the old state, inside `TransitionError`. Sleep, cycle and gyro standby are only
+//! changed by transitions and `reset_device`, which leaves the chip `Asleep`. The exceptions are
+//! the raw register writes (`write_byte`, `write_bit`, `write_bits`): writing PWR_MGMT_1 or
+//! PWR_MGMT_2 with them voids the guarantee that the type matches the chip.
+//!
+//! ```no_run
+//! use mpu6050::{Mpu6050, device::LP_WAKE_CTRL};
+//! use linux_embedded_hal::{I2cdev, Delay};
+//!
+//! let mpu = Mpu6050::new_asleep(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut mpu = mpu.into_awake(&mut Delay).unwrap();
+//! let gyro = mpu.get_gyro().unwrap();
+//!
+//! let mut mpu = mpu.into_low_power_accel(LP_WAKE_CTRL::_5).unwrap();
+//! let acc = mpu.get_acc().unwrap();
+//! ```
+//!
+//! ```compile_fail
+//! use mpu6050::Mpu6050;
+//! use linux_embedded_hal::I2cdev;
+//!
+//! let mut mpu = Mpu6050::new_asleep(I2cdev::new("/dev/i2c-1").unwrap());
+//! let gyro = mpu.get_gyro(); // asleep: no gyro data
+//! ```
+
+// the error hands back the driver, which is no larger than the Ok value, and no_std can't box it
+#![allow(clippy::result_large_err)]
+
+use crate::device::*;
+use crate::{Mpu6050, Mpu6050Error};
+use core::fmt;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// All sensors running
+#[derive(Copy, Clone, Debug)]
+pub struct Awake;
+
+/// Sleep mode, sensors off
+#[derive(Copy, Clone, Debug)]
+pub struct Asleep;
+
+/// Cycle mode: single accelerometer samples at the wake up frequency, gyros and temperature
+/// sensor off. Temperature compensation in `get_acc` uses the last temperature before the switch
+#[derive(Copy, Clone, Debug)]
+pub struct LowPowerAccel;
+
+/// Power states with valid accelerometer data
+pub trait AccelAvailable: sealed::Sealed {}
+
+impl sealed::Sealed for Awake {}
+impl sealed::Sealed for Asleep {}
+impl sealed::Sealed for LowPowerAccel {}
+impl AccelAvailable for Awake {}
+impl AccelAvailable for LowPowerAccel {}
+
+/// Failed power state transition, with the driver in its previous state
+pub struct TransitionError<I2C, MODE, E> {
+    /// Driver, unchanged power state
+    pub driver: Mpu6050<I2C, MODE>,
+    /// Cause
+    pub error: Mpu6050Error<E>,
+}
+
+impl<I2C, MODE, E: fmt::Debug> fmt::Debug for TransitionError<I2C, MODE, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransitionError").field("error", &self.error).finish()
+    }
+}
+
+impl<I2C, MODE, E> From<TransitionError<I2C, MODE, E>> for Mpu6050Error<E> {
+    fn from(e: TransitionError<I2C, MODE, E>) -> Self {
+        e.error
+    }
+}
+
+/// Result of a transition into `NEW`
+pub type Transition<I2C, MODE, NEW, E> = Result<Mpu6050<I2C, NEW>, TransitionError<I2C, MODE, E>>;
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Apply register changes, then change the type
+    pub(crate) fn transition<NEW, F>(mut self, apply: F) -> Transition<I2C, MODE, NEW, E>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Mpu6050Error<E>>,
+    {
+        match apply(&mut self) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(error) => Err(TransitionError { driver: self, error }),
+        }
+    }
+
+    /// Gyros and temperature sensor on, X gyro clock, as cycle mode may have left them
+    fn restore_sensors(&mut self) -> Result<(), Mpu6050Error<E>> {
+        self.set_gyro_standby(false)?;
+        self.write_temp_enabled(true)?;
+        self.write_clock_source(CLKSEL::GXAXIS)
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C, Asleep>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Side effect free constructor for a chip in its power on state, which is asleep
+    pub fn new_asleep(i2c: I2C) -> Self {
+        Mpu6050::new(i2c).into_mode()
+    }
+
+    /// Leave sleep mode: gyros and temperature sensor on, X gyro clock, and wait for the sensors to start up
+    pub fn into_awake<D: DelayNs>(self, delay: &mut D) -> Transition<I2C, Asleep, Awake, E> {
+        self.transition(|mpu| {
+            mpu.restore_sensors()?;
+            mpu.set_sleep_enabled(false)?;
+            delay.delay_ms(100u32);
+            Ok(())
+        })
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C, Awake>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Enter sleep mode
+    pub fn into_asleep(self) -> Transition<I2C, Awake, Asleep, E> {
+        self.transition(|mpu| mpu.set_sleep_enabled(true))
+    }
+
+    /// Enter cycle mode, waking up with `freq` for single accelerometer samples. Switches to the
+    /// internal oscillator, as the gyros are put in standby
+    pub fn into_low_power_accel(self, freq: LP_WAKE_CTRL) -> Transition<I2C, Awake, LowPowerAccel, E> {
+        self.transition(|mpu| {
+            mpu.write_clock_source(CLKSEL::OSCILL)?;
+            mpu.set_wake_frequency(freq)?;
+            mpu.set_gyro_standby(true)?;
+            mpu.write_temp_enabled(false)?;
+            mpu.set_sleep_enabled(false)?;
+            mpu.set_cycle_enabled(true)
+        })
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C, LowPowerAccel>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Leave cycle mode: gyros and temperature sensor on, X gyro clock, and wait for start up
+    pub fn into_awake<D: DelayNs>(self, delay: &mut D) -> Transition<I2C, LowPowerAccel, Awake, E> {
+        self.transition(|mpu| {
+            mpu.set_cycle_enabled(false)?;
+            mpu.restore_sensors()?;
+            delay.delay_ms(100u32);
+            Ok(())
+        })
+    }
+
+    /// Leave cycle mode and sleep
+    pub fn into_asleep(self) -> Transition<I2C, LowPowerAccel, Asleep, E> {
+        self.transition(|mpu| {
+            mpu.set_cycle_enabled(false)?;
+            mpu.set_sleep_enabled(true)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMpu6050, NoDelay};
+
+    #[test]
+    fn power_state_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([0, 0, 16384]);
+
+        let mpu = Mpu6050::new_asleep(mock);
+        let mut mpu = mpu.into_awake(&mut NoDelay).unwrap();
+        assert!(!mpu.get_sleep_enabled().unwrap());
+        assert_eq!(mpu.get_gyro().unwrap(), nalgebra::Vector3::zeros());
+
+        let mut mpu = mpu.into_low_power_accel(LP_WAKE_CTRL::_5).unwrap();
+        assert_eq!(mpu.get_acc().unwrap().z, 1.);
+        assert!(mpu.get_cycle_enabled().unwrap() && mpu.get_gyro_standby().unwrap());
+        assert_eq!(mpu.get_clock_source().unwrap(), CLKSEL::OSCILL);
+
+        let mut mpu = mpu.into_asleep().unwrap();
+        assert!(mpu.get_sleep_enabled().unwrap() && !mpu.get_cycle_enabled().unwrap());
+
+        // a failed transition hands the driver back
+        mpu.i2c.fail_next(1);
+        let mpu = match mpu.into_awake(&mut NoDelay) {
+            Err(TransitionError { driver, error: Mpu6050Error::I2c(_) }) => driver,
+            _ => panic!("expected bus error"),
+        };
+        let mut mpu = mpu.into_awake(&mut NoDelay).unwrap();
+        assert!(!mpu.get_sleep_enabled().unwrap());
+
+        // low power accel -> asleep -> awake ends with all sensors running
+        assert!(!mpu.get_gyro_standby().unwrap());
+        assert!(mpu.get_temp_enabled().unwrap());
+        assert_eq!(mpu.get_clock_source().unwrap(), CLKSEL::GXAXIS);
+
+        // a reset puts the chip to sleep, and the driver with it
+        let mut mpu: Mpu6050<_, Asleep> = mpu.reset_device(&mut NoDelay).unwrap();
+        assert!(mpu.get_sleep_enabled().unwrap());
+    }
+}
@@ -0,0 +1,381 @@
+//! Surviving bus glitches: retries of idempotent reads and re-initialisation of the chip.
+//!
+//! * `Retry` wraps the bus and repeats failed MPU register reads with exponential backoff. Writes,
+//!   reads with side effects (INT_STATUS, I2C_MST_STATUS, MOT_DETECT_STATUS, FIFO_R_W) and
+//!   transfers to other addresses, e.g. the AK8963 in bypass mode, are never repeated
+//! * `Mpu6050::supervise` runs an operation and counts consecutive bus failures. After
+//!   `Supervisor::new(max_failures)` of them, or an unexpected WHO_AM_I, the chip is reset, woken
+//!   and its configuration re-applied, reported as `Supervised::Recovered`
+//!
+//! The configuration restored is the content of the register cache (see
+//! `set_register_cache_enabled`), without it only the ranges. An AK8963 magnetometer is not
+//! restored: it may have lost power as well, so `get_mag` fails with `MagError::NotInitialized`
+//! until `init_magnetometer` runs again.
+//!
+//! ```no_run
+//! use mpu6050::{Mpu6050, recovery::*};
+//! use linux_embedded_hal::{I2cdev, Delay};
+//!
+//! let i2c = Retry::new(I2cdev::new("/dev/i2c-1").unwrap(), Delay, RetryPolicy::new(3, 100));
+//! let mut mpu = Mpu6050::new(i2c);
+//! mpu.set_register_cache_enabled(true);
+//! mpu.init(&mut Delay).unwrap();
+//!
+//! let mut supervisor = Supervisor::new(5);
+//! loop {
+//!     match mpu.supervise(&mut Delay, &mut supervisor, |mpu| mpu.get_acc()) {
+//!         Ok(Supervised::Value(acc)) => println!("acc: {:?}", acc),
+//!         Ok(Supervised::Recovered(recovery)) => println!("recovered: {:?}", recovery),
+//!         Err(e) => println!("error: {:?}", e),
+//!     }
+//! }
+//! ```
+
+use crate::device::*;
+use crate::power::Awake;
+use crate::registers::{FIFO_R_W, SNAPSHOT_LEN};
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+
+/// How often and how patiently `Retry` repeats a failed read
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one
+    pub attempts: u8,
+    /// Delay before the first retry in µs, doubled for each further retry
+    pub backoff_us: u32,
+}
+
+impl RetryPolicy {
+    /// `attempts` in total, the first retry after `backoff_us`
+    pub fn new(attempts: u8, backoff_us: u32) -> Self {
+        RetryPolicy { attempts, backoff_us }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// 3 attempts, 100 µs backoff
+    fn default() -> Self {
+        RetryPolicy::new(3, 100)
+    }
+}
+
+/// `I2c` wrapper retrying register reads of the MPU according to a `RetryPolicy`
+pub struct Retry<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    policy: RetryPolicy,
+    address: u8,
+    retries: u32,
+}
+
+impl<I2C, D> Retry<I2C, D>
+where
+    I2C: I2c,
+    D: DelayNs,
+{
+    /// Wrap `i2c` for an MPU at `DEFAULT_SLAVE_ADDR`, `delay` waits between attempts
+    pub fn new(i2c: I2C, delay: D, policy: RetryPolicy) -> Self {
+        Self::new_with_addr(i2c, delay, policy, DEFAULT_SLAVE_ADDR)
+    }
+
+    /// Wrap `i2c` for an MPU at `address`, see `Mpu6050::new_with_addr`
+    pub fn new_with_addr(i2c: I2C, delay: D, policy: RetryPolicy, address: u8) -> Self {
+        Retry { i2c, delay, policy, address, retries: 0 }
+    }
+
+    /// Number of retries so far
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Wrapped bus
+    pub fn inner(&mut self) -> &mut I2C {
+        &mut self.i2c
+    }
+
+    /// Destroy the wrapper and give back bus and delay
+    pub fn into_inner(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+}
+
+/// Registers with read side effects: INT_STATUS, I2C_MST_STATUS and MOT_DETECT_STATUS clear on
+/// read, FIFO_R_W pops data
+const READ_SIDE_EFFECTS: [u8; 4] = [INT_STATUS::ADDR, I2C_MST_STATUS::ADDR, MOT_DETECT_STATUS::ADDR, FIFO_R_W];
+
+/// MPU register read without side effects: register address, then data, not covering
+/// `READ_SIDE_EFFECTS`. Other devices are unknown, e.g. AK8963 ST1 and ST2 change on read
+fn is_idempotent(address: u8, mpu_address: u8, operations: &[Operation<'_>]) -> bool {
+    if address != mpu_address {
+        return false;
+    }
+    match operations {
+        [Operation::Write([reg]), Operation::Read(buf)] => {
+            let (start, end) = (*reg as usize, *reg as usize + buf.len());
+            !READ_SIDE_EFFECTS.iter().any(|r| (start..end).contains(&(*r as usize)))
+        }
+        _ => false,
+    }
+}
+
+impl<I2C: I2c, D> ErrorType for Retry<I2C, D> {
+    type Error = I2C::Error;
+}
+
+impl<I2C, D> I2c for Retry<I2C, D>
+where
+    I2C: I2c,
+    D: DelayNs,
+{
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        let attempts = if is_idempotent(address, self.address, operations) { self.policy.attempts.max(1) } else { 1 };
+        let mut backoff = self.policy.backoff_us;
+        let mut attempt = 1;
+        loop {
+            match self.i2c.transaction(address, operations) {
+                Err(_) if attempt < attempts => {
+                    self.delay.delay_us(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                    self.retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Why the chip was re-initialised
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RecoveryCause {
+    /// This many bus failures in a row
+    RepeatedFailures(u8),
+    /// WHO_AM_I read this value, not the one of the detected part
+    UnexpectedChipId(u8),
+}
+
+/// Re-initialisation of the chip, see `Mpu6050::recover`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Recovery {
+    /// Trigger
+    pub cause: RecoveryCause,
+    /// Part found afterwards
+    pub variant: ChipVariant,
+    /// Number of registers re-applied
+    pub restored: u8,
+}
+
+/// Outcome of `Mpu6050::supervise`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Supervised<T> {
+    /// The operation succeeded
+    Value(T),
+    /// The operation failed and the chip was re-initialised, the operation can be repeated
+    Recovered(Recovery),
+}
+
+/// Consecutive failure count for `Mpu6050::supervise`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Supervisor {
+    max_failures: u8,
+    failures: u8,
+    recoveries: u32,
+}
+
+impl Supervisor {
+    /// Recover after `max_failures` bus failures in a row
+    pub fn new(max_failures: u8) -> Self {
+        Supervisor { max_failures: max_failures.max(1), failures: 0, recoveries: 0 }
+    }
+
+    /// Bus failures in a row so far
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+
+    /// Number of recoveries so far
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C, Awake>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Run `op`. Bus failures are returned until `supervisor` saw enough in a row, then the chip
+    /// is recovered (see `recover`). A WHO_AM_I mismatch recovers right away
+    pub fn supervise<T, D, F>(&mut self, delay: &mut D, supervisor: &mut Supervisor, op: F) -> Result<Supervised<T>, Mpu6050Error<E>>
+    where
+        D: DelayNs,
+        F: FnOnce(&mut Self) -> Result<T, Mpu6050Error<E>>,
+    {
+        let cause = match op(self) {
+            Ok(value) => {
+                supervisor.failures = 0;
+                return Ok(Supervised::Value(value));
+            }
+            Err(Mpu6050Error::I2c(e)) => {
+                supervisor.failures = supervisor.failures.saturating_add(1);
+                if supervisor.failures < supervisor.max_failures {
+                    return Err(Mpu6050Error::I2c(e));
+                }
+                RecoveryCause::RepeatedFailures(supervisor.failures)
+            }
+            Err(Mpu6050Error::InvalidChipId(id)) => RecoveryCause::UnexpectedChipId(id),
+            Err(e) => return Err(e),
+        };
+
+        let recovery = self.recover(delay, cause)?;
+        supervisor.failures = 0;
+        supervisor.recoveries += 1;
+        Ok(Supervised::Recovered(recovery))
+    }
+
+    /// Compare WHO_AM_I with the detected part, fails with `InvalidChipId` on a mismatch.
+    /// Run it through `supervise` to recover
+    pub fn check_chip_id(&mut self) -> Result<(), Mpu6050Error<E>> {
+        let id = self.read_byte(WHOAMI)?;
+        if id == self.variant.who_am_i() {
+            Ok(())
+        } else {
+            Err(Mpu6050Error::InvalidChipId(id))
+        }
+    }
+
+    /// Reset and wake the chip, identify it again and re-apply the cached registers, or the ranges
+    /// without register cache. Calibration and other driver state is kept, the magnetometer has to
+    /// be initialised again
+    pub fn recover<D: DelayNs>(&mut self, delay: &mut D, cause: RecoveryCause) -> Result<Recovery, Mpu6050Error<E>> {
+        let cached = self.register_cache;
+        let (accel_range, gyro_range) = (self.accel_range_from_sensitivity(), self.gyro_range_from_sensitivity());
+        // the AK8963 is back in power down after a brown out, its data would stay frozen
+        self.magnetometer = None;
+
+        self.reset(delay)?;
+        self.write_byte(PWR_MGMT_1::ADDR, 0x01)?;
+        delay.delay_ms(100u32);
+        let variant = self.detect_variant()?;
+
+        let mut restored = 0;
+        match cached {
+            Some(cache) => {
+                // address order puts the power management registers last
+                for reg in 0..SNAPSHOT_LEN as u8 {
+                    if let Some(value) = cache.get(reg) {
+                        self.write_byte(reg, value)?;
+                        restored += 1;
+                    }
+                }
+            }
+            None => {
+                self.set_accel_range(accel_range)?;
+                self.set_gyro_range(gyro_range)?;
+                restored = 2;
+            }
+        }
+        Ok(Recovery { cause, variant, restored })
+    }
+
+    fn accel_range_from_sensitivity(&self) -> AccelRange {
+        let ranges = [AccelRange::G2, AccelRange::G4, AccelRange::G8, AccelRange::G16];
+        ranges.iter().copied().find(|r| r.sensitivity() == self.acc_sensitivity).unwrap_or(AccelRange::G2)
+    }
+
+    fn gyro_range_from_sensitivity(&self) -> GyroRange {
+        let ranges = [GyroRange::D250, GyroRange::D500, GyroRange::D1000, GyroRange::D2000];
+        ranges.iter().copied().find(|r| r.sensitivity() == self.gyro_sensitivity).unwrap_or(GyroRange::D250)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ak8963::{MagAccess, MagError, MagMode, MagResolution, AK8963_ADDR};
+    use crate::mock::{MockError, MockMpu6050, NoDelay};
+
+    #[test]
+    fn retry_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([0, 0, 16384]);
+        let mut mpu = Mpu6050::new(Retry::new(mock, NoDelay, RetryPolicy::default()));
+
+        mpu.i2c.inner().fail_next(2);
+        assert_eq!(mpu.get_acc().unwrap().z, 1.);
+        assert_eq!(mpu.i2c.retries(), 2);
+
+        // exhausted, writes and clear on read registers aren't retried
+        mpu.i2c.inner().fail_next(3);
+        assert!(matches!(mpu.get_acc(), Err(Mpu6050Error::I2c(MockError::Bus))));
+        mpu.i2c.inner().fail_next(1);
+        assert!(mpu.write_byte(PWR_MGMT_1::ADDR, 0x01).is_err());
+        mpu.i2c.inner().fail_next(1);
+        assert!(mpu.get_motion_detected().is_err());
+        mpu.i2c.inner().fail_next(1);
+        assert!(mpu.read_byte(I2C_MST_STATUS::ADDR).is_err());
+        mpu.i2c.inner().fail_next(1);
+        assert!(mpu.read_byte(MOT_DETECT_STATUS::ADDR).is_err());
+        assert_eq!(mpu.i2c.retries(), 4);
+
+        // only reads of the MPU: the AK8963 in bypass mode releases its data lock on read
+        mpu.i2c.inner().fail_next(1);
+        assert!(mpu.i2c.write_read(AK8963_ADDR, &[0x02], &mut [0; 8]).is_err());
+        assert_eq!(mpu.i2c.retries(), 4);
+
+        let mock = MockMpu6050::new_with_addr(0x69);
+        let mut mpu = Mpu6050::new_with_addr(Retry::new_with_addr(mock, NoDelay, RetryPolicy::default(), 0x69), 0x69);
+        mpu.i2c.inner().fail_next(1);
+        mpu.get_acc().unwrap();
+        assert_eq!(mpu.i2c.retries(), 1);
+    }
+
+    #[test]
+    fn recovery_test() {
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.set_register_cache_enabled(true);
+        mpu.init(&mut NoDelay).unwrap();
+        mpu.set_accel_range(AccelRange::G8).unwrap();
+        mpu.set_dlpf(DLPF_CFG::_44).unwrap();
+
+        // brown out: the chip lost its configuration and stops answering for a while
+        mpu.i2c.reset();
+        mpu.i2c.fail_next(2);
+        let mut supervisor = Supervisor::new(2);
+        let mut read = |mpu: &mut Mpu6050<MockMpu6050>| mpu.supervise(&mut NoDelay, &mut supervisor, |mpu| mpu.get_temp());
+        assert!(matches!(read(&mut mpu), Err(Mpu6050Error::I2c(_))));
+        match read(&mut mpu).unwrap() {
+            Supervised::Recovered(recovery) => {
+                assert_eq!(recovery.cause, RecoveryCause::RepeatedFailures(2));
+                assert_eq!(recovery.variant, ChipVariant::Mpu6050);
+            }
+            _ => panic!("expected recovery"),
+        }
+        assert!(matches!(read(&mut mpu), Ok(Supervised::Value(_))));
+        assert_eq!(supervisor.recoveries(), 1);
+        assert_eq!(mpu.i2c.register(ACCEL_CONFIG::ADDR), 0x10);
+        assert_eq!(mpu.i2c.register(CONFIG::ADDR), DLPF_CFG::_44 as u8);
+        assert_eq!(mpu.i2c.register(PWR_MGMT_1::ADDR), 0x01);
+
+        // another part answers, without register cache only the ranges are restored
+        mpu.set_register_cache_enabled(false);
+        mpu.i2c.set_who_am_i(0x70);
+        let recovered = mpu.supervise(&mut NoDelay, &mut supervisor, |mpu| mpu.check_chip_id()).unwrap();
+        assert_eq!(recovered, Supervised::Recovered(Recovery {
+            cause: RecoveryCause::UnexpectedChipId(0x70),
+            variant: ChipVariant::Mpu6500,
+            restored: 2,
+        }));
+        assert_eq!(mpu.get_accel_range().unwrap(), AccelRange::G8);
+
+        // the magnetometer needs a new init
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x71);
+        let mut mpu = Mpu6050::new(mock);
+        mpu.init(&mut NoDelay).unwrap();
+        mpu.init_magnetometer(&mut NoDelay, MagAccess::Bypass, MagMode::Continuous8Hz, MagResolution::Bits16).unwrap();
+        mpu.recover(&mut NoDelay, RecoveryCause::RepeatedFailures(1)).unwrap();
+        assert!(matches!(mpu.get_mag(), Err(Mpu6050Error::Magnetometer(MagError::NotInitialized))));
+    }
+}
@@ -0,0 +1,463 @@
+//! Full register map snapshot for diagnostics: dump, pretty-print, diff and restore.
+//!
+//! ```no_run
+//! use mpu6050::Mpu6050;
+//! use linux_embedded_hal::I2cdev;
+//!
+//! let mut mpu = Mpu6050::new(I2cdev::new("/dev/i2c-1").unwrap());
+//! let before = mpu.dump_registers().unwrap();
+//! mpu.set_accel_range(mpu6050::device::AccelRange::G8).unwrap();
+//! let after = mpu.dump_registers().unwrap();
+//!
+//! for change in before.diff(&after) {
+//!     // 0x1c ACCEL_CONFIG 0x00 -> 0x10  FS_SEL 0 -> 2
+//!     println!("{}", change);
+//! }
+//! mpu.restore_registers(&before).unwrap();
+//! ```
+
+use crate::bits;
+use crate::device::*;
+use crate::{Mpu6050, Mpu6050Error};
+use core::fmt;
+use embedded_hal::i2c::I2c;
+
+/// Number of documented registers, 0x00 - 0x75
+pub const SNAPSHOT_LEN: usize = WHOAMI as usize + 1;
+
+/// FIFO read/write register, reading it pops the FIFO
+pub const FIFO_R_W: u8 = 0x74;
+
+/// Name of a documented register
+pub fn register_name(reg: u8) -> Option<&'static str> {
+    const EXT_SENS_DATA: [&str; 24] = [
+        "EXT_SENS_DATA_00", "EXT_SENS_DATA_01", "EXT_SENS_DATA_02", "EXT_SENS_DATA_03",
+        "EXT_SENS_DATA_04", "EXT_SENS_DATA_05", "EXT_SENS_DATA_06", "EXT_SENS_DATA_07",
+        "EXT_SENS_DATA_08", "EXT_SENS_DATA_09", "EXT_SENS_DATA_10", "EXT_SENS_DATA_11",
+        "EXT_SENS_DATA_12", "EXT_SENS_DATA_13", "EXT_SENS_DATA_14", "EXT_SENS_DATA_15",
+        "EXT_SENS_DATA_16", "EXT_SENS_DATA_17", "EXT_SENS_DATA_18", "EXT_SENS_DATA_19",
+        "EXT_SENS_DATA_20", "EXT_SENS_DATA_21", "EXT_SENS_DATA_22", "EXT_SENS_DATA_23",
+    ];
+
+    Some(match reg {
+        0x0d => "SELF_TEST_X",
+        0x0e => "SELF_TEST_Y",
+        0x0f => "SELF_TEST_Z",
+        0x10 => "SELF_TEST_A",
+        SMPLRT_DIV => "SMPLRT_DIV",
+        CONFIG::ADDR => "CONFIG",
+        GYRO_CONFIG::ADDR => "GYRO_CONFIG",
+        ACCEL_CONFIG::ADDR => "ACCEL_CONFIG",
+        0x1d => "FF_THR",
+        0x1e => "FF_DUR",
+        MOT_THR => "MOT_THR",
+        MOT_DUR => "MOT_DUR",
+        0x21 => "ZRMOT_THR",
+        0x22 => "ZRMOT_DUR",
+        0x23 => "FIFO_EN",
+        0x24 => "I2C_MST_CTRL",
+        0x25 => "I2C_SLV0_ADDR",
+        0x26 => "I2C_SLV0_REG",
+        0x27 => "I2C_SLV0_CTRL",
+        0x28 => "I2C_SLV1_ADDR",
+        0x29 => "I2C_SLV1_REG",
+        0x2a => "I2C_SLV1_CTRL",
+        0x2b => "I2C_SLV2_ADDR",
+        0x2c => "I2C_SLV2_REG",
+        0x2d => "I2C_SLV2_CTRL",
+        0x2e => "I2C_SLV3_ADDR",
+        0x2f => "I2C_SLV3_REG",
+        0x30 => "I2C_SLV3_CTRL",
+        0x31 => "I2C_SLV4_ADDR",
+        0x32 => "I2C_SLV4_REG",
+        0x33 => "I2C_SLV4_DO",
+        0x34 => "I2C_SLV4_CTRL",
+        0x35 => "I2C_SLV4_DI",
+        0x36 => "I2C_MST_STATUS",
+        INT_PIN_CFG::ADDR => "INT_PIN_CFG",
+        INT_ENABLE::ADDR => "INT_ENABLE",
+        INT_STATUS::ADDR => "INT_STATUS",
+        0x3b => "ACCEL_XOUT_H",
+        0x3c => "ACCEL_XOUT_L",
+        0x3d => "ACCEL_YOUT_H",
+        0x3e => "ACCEL_YOUT_L",
+        0x3f => "ACCEL_ZOUT_H",
+        0x40 => "ACCEL_ZOUT_L",
+        0x41 => "TEMP_OUT_H",
+        0x42 => "TEMP_OUT_L",
+        0x43 => "GYRO_XOUT_H",
+        0x44 => "GYRO_XOUT_L",
+        0x45 => "GYRO_YOUT_H",
+        0x46 => "GYRO_YOUT_L",
+        0x47 => "GYRO_ZOUT_H",
+        0x48 => "GYRO_ZOUT_L",
+        0x49..=0x60 => EXT_SENS_DATA[(reg - 0x49) as usize],
+        MOT_DETECT_STATUS::ADDR => "MOT_DETECT_STATUS",
+        0x63 => "I2C_SLV0_DO",
+        0x64 => "I2C_SLV1_DO",
+        0x65 => "I2C_SLV2_DO",
+        0x66 => "I2C_SLV3_DO",
+        0x67 => "I2C_MST_DELAY_CTRL",
+        0x68 => "SIGNAL_PATH_RESET",
+        MOT_DETECT_CONTROL::ADDR => "MOT_DETECT_CTRL",
+        0x6a => "USER_CTRL",
+        PWR_MGMT_1::ADDR => "PWR_MGMT_1",
+        PWR_MGMT_2::ADDR => "PWR_MGMT_2",
+        0x72 => "FIFO_COUNTH",
+        0x73 => "FIFO_COUNTL",
+        FIFO_R_W => "FIFO_R_W",
+        WHOAMI => "WHO_AM_I",
+        _ => return None,
+    })
+}
+
+/// Bit fields of a register as (name, start bit, length), for registers described in `device`
+pub fn register_fields(reg: u8) -> &'static [(&'static str, u8, u8)] {
+    match reg {
+        CONFIG::ADDR => &[
+            ("EXT_SYNC_SET", CONFIG::EXT_SYNC_SET.bit, CONFIG::EXT_SYNC_SET.length),
+            ("DLPF_CFG", CONFIG::DLPF_CFG.bit, CONFIG::DLPF_CFG.length),
+        ],
+        GYRO_CONFIG::ADDR => &[
+            ("XG_ST", GYRO_CONFIG::XG_ST, 1),
+            ("YG_ST", GYRO_CONFIG::YG_ST, 1),
+            ("ZG_ST", GYRO_CONFIG::ZG_ST, 1),
+            ("FS_SEL", GYRO_CONFIG::FS_SEL.bit, GYRO_CONFIG::FS_SEL.length),
+        ],
+        ACCEL_CONFIG::ADDR => &[
+            ("XA_ST", ACCEL_CONFIG::XA_ST, 1),
+            ("YA_ST", ACCEL_CONFIG::YA_ST, 1),
+            ("ZA_ST", ACCEL_CONFIG::ZA_ST, 1),
+            ("FS_SEL", ACCEL_CONFIG::FS_SEL.bit, ACCEL_CONFIG::FS_SEL.length),
+            ("ACCEL_HPF", ACCEL_CONFIG::ACCEL_HPF.bit, ACCEL_CONFIG::ACCEL_HPF.length),
+        ],
+        INT_PIN_CFG::ADDR => &[
+            ("INT_LEVEL", INT_PIN_CFG::INT_LEVEL, 1),
+            ("INT_OPEN", INT_PIN_CFG::INT_OPEN, 1),
+            ("LATCH_INT_EN", INT_PIN_CFG::LATCH_INT_EN, 1),
+            ("INT_RD_CLEAR", INT_PIN_CFG::INT_RD_CLEAR, 1),
+            ("FSYNC_INT_LEVEL", INT_PIN_CFG::FSYNC_INT_LEVEL, 1),
+            ("FSYNC_INT_EN", INT_PIN_CFG::FSYNC_INT_EN, 1),
+            ("I2C_BYPASS_EN", INT_PIN_CFG::I2C_BYPASS_EN, 1),
+            ("CLKOUT_EN", INT_PIN_CFG::CLKOUT_EN, 1),
+        ],
+        INT_ENABLE::ADDR => &[
+            ("FF_EN", INT_ENABLE::FF_EN, 1),
+            ("MOT_EN", INT_ENABLE::MOT_EN, 1),
+            ("ZMOT_EN", INT_ENABLE::ZMOT_EN, 1),
+            ("FIFO_OFLOW_EN", INT_ENABLE::FIFO_OFLOW_END, 1),
+            ("I2C_MST_INT_EN", INT_ENABLE::I2C_MST_INT_EN, 1),
+            ("DATA_RDY_EN", INT_ENABLE::DATA_RDY_EN, 1),
+        ],
+        INT_STATUS::ADDR => &[
+            ("FF_INT", INT_STATUS::FF_INT, 1),
+            ("MOT_INT", INT_STATUS::MOT_INT, 1),
+            ("ZMOT_INT", INT_STATUS::ZMOT_INT, 1),
+            ("FIFO_OFLOW_INT", INT_STATUS::FIFO_OFLOW_INT, 1),
+            ("I2C_MST_INT", INT_STATUS::I2C_MSF_INT, 1),
+            ("DATA_RDY_INT", INT_STATUS::DATA_RDY_INT, 1),
+        ],
+        MOT_DETECT_STATUS::ADDR => &[
+            ("MOT_XNEG", MOT_DETECT_STATUS::MOT_XNEG, 1),
+            ("MOT_XPOS", MOT_DETECT_STATUS::MOT_XPOS, 1),
+            ("MOT_YNEG", MOT_DETECT_STATUS::MOT_YNEG, 1),
+            ("MOT_YPOS", MOT_DETECT_STATUS::MOT_YPOS, 1),
+            ("MOT_ZNEG", MOT_DETECT_STATUS::MOT_ZNEG, 1),
+            ("MOT_ZPOS", MOT_DETECT_STATUS::MOT_ZPOS, 1),
+            ("MOT_ZRMOT", MOT_DETECT_STATUS::MOT_ZRMOT, 1),
+        ],
+        MOT_DETECT_CONTROL::ADDR => &[
+            ("ACCEL_ON_DELAY", MOT_DETECT_CONTROL::ACCEL_ON_DELAY.bit, MOT_DETECT_CONTROL::ACCEL_ON_DELAY.length),
+            ("FF_COUNT", MOT_DETECT_CONTROL::FF_COUNT.bit, MOT_DETECT_CONTROL::FF_COUNT.length),
+            ("MOT_COUNT", MOT_DETECT_CONTROL::MOT_COUNT.bit, MOT_DETECT_CONTROL::MOT_COUNT.length),
+        ],
+        PWR_MGMT_1::ADDR => &[
+            ("DEVICE_RESET", PWR_MGMT_1::DEVICE_RESET, 1),
+            ("SLEEP", PWR_MGMT_1::SLEEP, 1),
+            ("CYCLE", PWR_MGMT_1::CYCLE, 1),
+            ("TEMP_DIS", PWR_MGMT_1::TEMP_DIS, 1),
+            ("CLKSEL", PWR_MGMT_1::CLKSEL.bit, PWR_MGMT_1::CLKSEL.length),
+        ],
+        PWR_MGMT_2::ADDR => &[
+            ("LP_WAKE_CTRL", PWR_MGMT_2::LP_WAKE_CTRL.bit, PWR_MGMT_2::LP_WAKE_CTRL.length),
+            ("STBY_XA", PWR_MGMT_2::STBY_XA, 1),
+            ("STBY_YA", PWR_MGMT_2::STBY_YA, 1),
+            ("STBY_ZA", PWR_MGMT_2::STBY_ZA, 1),
+            ("STBY_XG", PWR_MGMT_2::STBY_XG, 1),
+            ("STBY_YG", PWR_MGMT_2::STBY_YG, 1),
+            ("STBY_ZG", PWR_MGMT_2::STBY_ZG, 1),
+        ],
+        _ => &[],
+    }
+}
+
+/// Whether a register is read only: status, sensor data, aux slave 4 input, FIFO count, WHO_AM_I
+pub fn is_read_only(reg: u8) -> bool {
+    matches!(reg,
+        0x35 // I2C_SLV4_DI
+        | 0x36 // I2C_MST_STATUS
+        | 0x3a..=0x61 // INT_STATUS, sensor data, EXT_SENS_DATA, MOT_DETECT_STATUS
+        | 0x72 | 0x73 // FIFO_COUNT
+        | 0x75 // WHO_AM_I
+    )
+}
+
+/// Bits masked out when restoring a register: one shot resets that must not be replayed
+fn restore_mask(reg: u8) -> u8 {
+    match reg {
+        PWR_MGMT_1::ADDR => !(1 << PWR_MGMT_1::DEVICE_RESET),
+        0x34 => !0x80, // I2C_SLV4_CTRL: I2C_SLV4_EN starts a transfer
+        0x6a => !0x07, // USER_CTRL: FIFO_RESET, I2C_MST_RESET, SIG_COND_RESET
+        _ => 0xff,
+    }
+}
+
+/// Whether `restore_registers` writes the register: documented, writable, no FIFO data,
+/// no factory self test trim and no signal path reset
+pub fn is_restorable(reg: u8) -> bool {
+    register_name(reg).is_some()
+        && !is_read_only(reg)
+        && !matches!(reg, 0x0d..=0x10 | 0x68 | FIFO_R_W)
+}
+
+/// Shadow copy of restorable registers, filled on first read or write
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RegisterCache {
+    values: [u8; SNAPSHOT_LEN],
+    valid: u128,
+}
+
+impl RegisterCache {
+    pub(crate) fn new() -> Self {
+        RegisterCache { values: [0; SNAPSHOT_LEN], valid: 0 }
+    }
+
+    /// Cached value, None if unknown or not cacheable
+    pub(crate) fn get(&self, reg: u8) -> Option<u8> {
+        if (reg as usize) < SNAPSHOT_LEN && self.valid & (1 << reg) != 0 {
+            Some(self.values[reg as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Remember a value read from or written to the chip, without self clearing reset bits.
+    /// I2C_SLV4_CTRL isn't cached, so that each write of I2C_SLV4_EN starts a transfer
+    pub(crate) fn set(&mut self, reg: u8, value: u8) {
+        if is_restorable(reg) && reg != 0x34 {
+            self.values[reg as usize] = value & restore_mask(reg);
+            self.valid |= 1 << reg;
+        }
+    }
+
+    /// Track a write: a device reset restores unknown reset values
+    pub(crate) fn update(&mut self, reg: u8, value: u8) {
+        if reg == PWR_MGMT_1::ADDR && value & (1 << PWR_MGMT_1::DEVICE_RESET) != 0 {
+            self.clear();
+        } else {
+            self.set(reg, value);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.valid = 0;
+    }
+}
+
+/// Values of registers 0x00 - 0x75. FIFO_R_W is not read, it reads as 0
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RegisterSnapshot {
+    registers: [u8; SNAPSHOT_LEN],
+}
+
+impl RegisterSnapshot {
+    /// Snapshot from raw values
+    pub fn new(registers: [u8; SNAPSHOT_LEN]) -> Self {
+        RegisterSnapshot { registers }
+    }
+
+    /// Raw values
+    pub fn registers(&self) -> &[u8; SNAPSHOT_LEN] {
+        &self.registers
+    }
+
+    /// Value of a register, None outside 0x00 - 0x75
+    pub fn get(&self, reg: u8) -> Option<u8> {
+        self.registers.get(reg as usize).copied()
+    }
+
+    /// Value of a bit field, see `register_fields`
+    pub fn field(&self, reg: u8, bit: u8, length: u8) -> Option<u8> {
+        self.get(reg).map(|byte| bits::get_bits(byte, bit, length))
+    }
+
+    /// Registers that differ between self and other
+    pub fn diff<'a>(&'a self, other: &'a RegisterSnapshot) -> impl Iterator<Item = RegisterChange> + 'a {
+        (0..SNAPSHOT_LEN as u8)
+            .filter(move |reg| self.registers[*reg as usize] != other.registers[*reg as usize])
+            .map(move |reg| RegisterChange {
+                reg,
+                before: self.registers[reg as usize],
+                after: other.registers[reg as usize],
+            })
+    }
+}
+
+/// Writes one line per documented register: address, name, value and decoded bit fields
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for reg in 0..SNAPSHOT_LEN as u8 {
+            if let Some(name) = register_name(reg) {
+                let value = self.registers[reg as usize];
+                write!(f, "{:#04x} {:<18} {:#04x}", reg, name, value)?;
+                for (field, bit, length) in register_fields(reg) {
+                    write!(f, " {}={}", field, bits::get_bits(value, *bit, *length))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One register differing between two snapshots
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RegisterChange {
+    /// Register address
+    pub reg: u8,
+    /// Value in the first snapshot
+    pub before: u8,
+    /// Value in the second snapshot
+    pub after: u8,
+}
+
+/// Address, name, both values and the bit fields that changed
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04x} {} {:#04x} -> {:#04x}", self.reg, register_name(self.reg).unwrap_or("?"), self.before, self.after)?;
+        for (field, bit, length) in register_fields(self.reg) {
+            let (before, after) = (bits::get_bits(self.before, *bit, *length), bits::get_bits(self.after, *bit, *length));
+            if before != after {
+                write!(f, "  {} {} -> {}", field, before, after)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Reads registers 0x00 - 0x75. Skips FIFO_R_W, but note that reading INT_STATUS clears it
+    pub fn dump_registers(&mut self) -> Result<RegisterSnapshot, Mpu6050Error<E>> {
+        let mut registers = [0; SNAPSHOT_LEN];
+        self.read_bytes(0x00, &mut registers[..FIFO_R_W as usize])?;
+        registers[WHOAMI as usize] = self.read_byte(WHOAMI)?;
+        Ok(RegisterSnapshot::new(registers))
+    }
+
+    /// Writes back all registers of the snapshot for which `is_restorable` holds, without
+    /// triggering device, FIFO or signal path resets. With the register cache enabled, only
+    /// registers that differ from the cache are written
+    pub fn restore_registers(&mut self, snapshot: &RegisterSnapshot) -> Result<(), Mpu6050Error<E>> {
+        for reg in (0..SNAPSHOT_LEN as u8).filter(|reg| is_restorable(*reg)) {
+            self.write_byte(reg, snapshot.registers[reg as usize] & restore_mask(reg))?;
+        }
+        Ok(())
+    }
+
+    /// Keep a shadow copy of the writable configuration registers (see `is_restorable`).
+    /// Bit field reads are served from the copy and writes of unchanged values are skipped,
+    /// which removes most read-modify-write traffic. The copy fills as registers are accessed
+    pub fn set_register_cache_enabled(&mut self, enable: bool) {
+        self.register_cache = if enable { Some(RegisterCache::new()) } else { None };
+    }
+
+    /// Whether the register cache is in use
+    pub fn get_register_cache_enabled(&self) -> bool {
+        self.register_cache.is_some()
+    }
+
+    /// Forget all cached values, e.g. after the chip was reset or written by someone else
+    pub fn invalidate_register_cache(&mut self) {
+        if let Some(cache) = self.register_cache.as_mut() {
+            cache.clear();
+        }
+    }
+
+    /// Compares every cached register with the chip and adopts the chip's value.
+    /// Returns the number of registers that differed, e.g. after a brown out reset
+    pub fn verify_register_cache(&mut self) -> Result<u8, Mpu6050Error<E>> {
+        let cached = match self.register_cache {
+            Some(cache) => cache,
+            None => return Ok(0),
+        };
+
+        // reads refill the emptied cache from the chip
+        self.invalidate_register_cache();
+        let mut mismatches = 0;
+        for reg in 0..SNAPSHOT_LEN as u8 {
+            if let Some(value) = cached.get(reg) {
+                if self.read_byte(reg)? != value {
+                    mismatches += 1;
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMpu6050;
+    extern crate std;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    #[test]
+    fn dump_diff_restore_test() {
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        let reset = mpu.dump_registers().unwrap();
+        assert_eq!(reset.get(PWR_MGMT_1::ADDR), Some(0x40));
+        assert_eq!(reset.get(WHOAMI), Some(0x68));
+
+        let text = reset.to_string();
+        assert!(text.contains("0x6b PWR_MGMT_1         0x40 DEVICE_RESET=0 SLEEP=1 CYCLE=0 TEMP_DIS=0 CLKSEL=0"));
+
+        mpu.set_accel_range(AccelRange::G8).unwrap();
+        mpu.set_gyro_range(GyroRange::D2000).unwrap();
+        mpu.set_sleep_enabled(false).unwrap();
+        let configured = mpu.dump_registers().unwrap();
+
+        let changes: Vec<RegisterChange> = reset.diff(&configured).collect();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[1].to_string(), "0x1c ACCEL_CONFIG 0x00 -> 0x10  FS_SEL 0 -> 2");
+
+        // device reset, then restore without resetting again
+        mpu.write_byte(PWR_MGMT_1::ADDR, 0x80).unwrap();
+        assert_eq!(mpu.dump_registers().unwrap(), reset);
+        mpu.restore_registers(&configured).unwrap();
+        assert_eq!(mpu.dump_registers().unwrap(), configured);
+    }
+
+    #[test]
+    fn slave4_cache_test() {
+        let mut mpu = Mpu6050::new(MockMpu6050::new());
+        mpu.set_register_cache_enabled(true);
+
+        // I2C_SLV4_DI is written by the aux master, never served from the cache
+        assert!(is_read_only(0x35) && !is_restorable(0x35));
+        mpu.i2c.set_register(0x35, 0x12);
+        assert_eq!(mpu.read_byte(0x35).unwrap(), 0x12);
+        mpu.i2c.set_register(0x35, 0x34);
+        assert_eq!(mpu.read_byte(0x35).unwrap(), 0x34);
+
+        // each write of I2C_SLV4_EN reaches the chip
+        let before = mpu.i2c.transactions();
+        mpu.write_byte(0x34, 0x80).unwrap();
+        mpu.write_byte(0x34, 0x80).unwrap();
+        assert_eq!(mpu.i2c.transactions(), before + 2);
+    }
+}
@@ -0,0 +1,409 @@
+//! Trajectory driven sensor simulation on top of `MockMpu6050`.
+//!
+//! A `Trajectory` describes orientation, angular velocity and acceleration over time. The
+//! `Simulator` samples it at the configured output rate (SMPLRT_DIV, DLPF_CFG), adds sensor errors
+//! (noise, bias, scale error), low pass filters according to DLPF_CFG, scales for the configured
+//! `AccelRange`/`GyroRange`, saturates and writes ACCEL/GYRO/TEMP registers. Noise comes from a
+//! seeded generator, so runs are reproducible. With the DLPF off, the gyro runs at 8 kHz and the
+//! accelerometer at 1 kHz. TEMP is scaled for the part in WHO_AM_I.
+//!
+//! The driver talks to the simulator like to a real bus. Time either advances manually with
+//! `advance`, or by a fixed amount with every bus transaction, see `set_auto_advance`:
+//!
+//! ```
+//! use mpu6050::{Mpu6050, simulator::*};
+//! use nalgebra::{UnitQuaternion, Vector3};
+//!
+//! let still = |_t: f32| MotionState::at_rest(UnitQuaternion::identity());
+//! let mut sim = Simulator::new(still, SensorErrors::default());
+//! sim.advance(0.01);
+//!
+//! let mut mpu = Mpu6050::new(&mut sim);
+//! assert!((mpu.get_acc().unwrap() - Vector3::z()).amax() < 1e-3);
+//! ```
+
+use crate::device::*;
+use crate::ins::STANDARD_GRAVITY;
+use crate::mock::{MockError, MockMpu6050};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use nalgebra::{UnitQuaternion, Vector3};
+
+/// Motion of the sensor at one point in time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MotionState {
+    /// Orientation, sensor to world frame (z up)
+    pub orientation: UnitQuaternion<f32>,
+    /// Angular velocity in the sensor frame, rad/s
+    pub angular_velocity: Vector3<f32>,
+    /// Linear acceleration in the world frame, without gravity, m/s²
+    pub acceleration: Vector3<f32>,
+}
+
+impl MotionState {
+    /// Sensor at rest with the given orientation
+    pub fn at_rest(orientation: UnitQuaternion<f32>) -> Self {
+        MotionState {
+            orientation,
+            angular_velocity: Vector3::zeros(),
+            acceleration: Vector3::zeros(),
+        }
+    }
+
+    /// Ideal accelerometer reading (specific force) in g, sensor frame
+    pub fn specific_force(&self) -> Vector3<f32> {
+        let world = self.acceleration / STANDARD_GRAVITY + Vector3::z();
+        self.orientation.inverse_transform_vector(&world)
+    }
+}
+
+/// Motion profile over time
+pub trait Trajectory {
+    /// State at time t, in s since simulation start
+    fn state(&mut self, t: f32) -> MotionState;
+}
+
+impl<F: FnMut(f32) -> MotionState> Trajectory for F {
+    fn state(&mut self, t: f32) -> MotionState {
+        self(t)
+    }
+}
+
+/// Sensor errors added to the ideal readings: reading = (1 + scale) * ideal + bias + noise
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorErrors {
+    /// Accelerometer white noise standard deviation per sample, g
+    pub acc_noise: f32,
+    /// Accelerometer bias, g
+    pub acc_bias: Vector3<f32>,
+    /// Accelerometer scale error, e.g. 0.03 for +3%
+    pub acc_scale: Vector3<f32>,
+    /// Gyro white noise standard deviation per sample, rad/s
+    pub gyro_noise: f32,
+    /// Gyro bias, rad/s
+    pub gyro_bias: Vector3<f32>,
+    /// Gyro scale error
+    pub gyro_scale: Vector3<f32>,
+    /// Die temperature, °C
+    pub temperature: f32,
+    /// Seed of the noise generator
+    pub seed: u32,
+}
+
+impl Default for SensorErrors {
+    /// Ideal sensor at 25 °C
+    fn default() -> Self {
+        SensorErrors {
+            acc_noise: 0.,
+            acc_bias: Vector3::zeros(),
+            acc_scale: Vector3::zeros(),
+            gyro_noise: 0.,
+            gyro_bias: Vector3::zeros(),
+            gyro_scale: Vector3::zeros(),
+            temperature: 25.,
+            seed: 1,
+        }
+    }
+}
+
+/// xorshift32 with Box-Muller, good enough for sensor noise and no_std
+#[derive(Copy, Clone, Debug)]
+struct Noise {
+    state: u32,
+}
+
+impl Noise {
+    fn new(seed: u32) -> Self {
+        Noise { state: seed.max(1) }
+    }
+
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        // (0, 1]
+        ((self.state >> 8) as f32 + 1.) / (1 << 24) as f32
+    }
+
+    fn gaussian(&mut self) -> f32 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        libm::sqrtf(-2. * libm::logf(u1)) * libm::cosf(2. * crate::PI * u2)
+    }
+
+    fn vector(&mut self, std: f32) -> Vector3<f32> {
+        if std == 0. {
+            return Vector3::zeros();
+        }
+        Vector3::new(self.gaussian(), self.gaussian(), self.gaussian()) * std
+    }
+}
+
+/// Accel and gyro bandwidth in Hz per DLPF_CFG, None: filter off. Register map, section 4.3
+const DLPF_BANDWIDTH: [(Option<f32>, Option<f32>); 8] = [
+    (None, None),
+    (Some(184.), Some(188.)),
+    (Some(94.), Some(98.)),
+    (Some(44.), Some(42.)),
+    (Some(21.), Some(20.)),
+    (Some(10.), Some(10.)),
+    (Some(5.), Some(5.)),
+    (None, None),
+];
+
+/// Gyro output rate with the DLPF off, Hz. Sample times count in ticks of this clock
+const BASE_RATE: u64 = 8000;
+/// Accelerometer output rate, Hz: with the DLPF off, the accel repeats samples at the gyro rate
+const ACCEL_RATE: u64 = 1000;
+
+/// Simulated MPU6050 following a trajectory
+pub struct Simulator<T> {
+    mock: MockMpu6050,
+    trajectory: T,
+    errors: SensorErrors,
+    noise: Noise,
+    time: f64,
+    next_tick: u64,
+    acc_period: Option<u64>,
+    auto_advance: Option<f32>,
+    acc_filtered: Option<Vector3<f32>>,
+    gyro_filtered: Option<Vector3<f32>>,
+}
+
+impl<T: Trajectory> Simulator<T> {
+    /// Simulator at t = 0 with a fresh register map. No sample is written before the first `advance`
+    pub fn new(trajectory: T, errors: SensorErrors) -> Self {
+        Self::new_with_mock(MockMpu6050::new(), trajectory, errors)
+    }
+
+    /// Same as `new`, but on top of a prepared register map, e.g. with a custom address
+    pub fn new_with_mock(mock: MockMpu6050, trajectory: T, errors: SensorErrors) -> Self {
+        Simulator {
+            mock,
+            trajectory,
+            errors,
+            noise: Noise::new(errors.seed),
+            time: 0.,
+            next_tick: 0,
+            acc_period: None,
+            auto_advance: None,
+            acc_filtered: None,
+            gyro_filtered: None,
+        }
+    }
+
+    /// Underlying register map
+    pub fn mock(&self) -> &MockMpu6050 {
+        &self.mock
+    }
+
+    /// Underlying register map, e.g. to inject bus errors
+    pub fn mock_mut(&mut self) -> &mut MockMpu6050 {
+        &mut self.mock
+    }
+
+    /// Change sensor errors, e.g. temperature during a sweep. The noise generator is not reseeded
+    pub fn set_errors(&mut self, errors: SensorErrors) {
+        self.errors = errors;
+    }
+
+    /// Simulation time, s
+    pub fn time(&self) -> f32 {
+        self.time as f32
+    }
+
+    /// Advance time by dt s with every bus transaction. `None`: only `advance` moves time
+    pub fn set_auto_advance(&mut self, dt: Option<f32>) {
+        self.auto_advance = dt;
+    }
+
+    /// Output data rate in Hz from DLPF_CFG and SMPLRT_DIV
+    pub fn sample_rate(&self) -> f32 {
+        BASE_RATE as f32 / self.sample_ticks() as f32
+    }
+
+    /// Sample period in ticks of `BASE_RATE`
+    fn sample_ticks(&self) -> u64 {
+        let dlpf = bits_of(self.mock.register(CONFIG::ADDR), &CONFIG::DLPF_CFG);
+        let gyro_ticks = if dlpf == 0 || dlpf == 7 { 1 } else { BASE_RATE / 1000 };
+        gyro_ticks * (1 + self.mock.register(SMPLRT_DIV) as u64)
+    }
+
+    /// Advance time by dt s, writing every sample due in between
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt as f64;
+        while self.next_tick as f64 <= self.time * BASE_RATE as f64 {
+            self.sample(self.next_tick);
+            self.next_tick += self.sample_ticks();
+        }
+    }
+
+    fn sample(&mut self, tick: u64) {
+        let t = (tick as f64 / BASE_RATE as f64) as f32;
+        let state = self.trajectory.state(t);
+        let e = self.errors;
+
+        let acc = state.specific_force().component_mul(&(Vector3::repeat(1.) + e.acc_scale))
+            + e.acc_bias + self.noise.vector(e.acc_noise);
+        let gyro = state.angular_velocity.component_mul(&(Vector3::repeat(1.) + e.gyro_scale))
+            + e.gyro_bias + self.noise.vector(e.gyro_noise);
+
+        let (acc_bw, gyro_bw) = DLPF_BANDWIDTH[bits_of(self.mock.register(CONFIG::ADDR), &CONFIG::DLPF_CFG) as usize];
+        let period = 1. / self.sample_rate();
+        let acc = Self::low_pass(&mut self.acc_filtered, acc, acc_bw, period);
+        let gyro = Self::low_pass(&mut self.gyro_filtered, gyro, gyro_bw, period);
+
+        let arange = AccelRange::from(bits_of(self.mock.register(ACCEL_CONFIG::ADDR), &ACCEL_CONFIG::FS_SEL));
+        let grange = GyroRange::from(bits_of(self.mock.register(GYRO_CONFIG::ADDR), &GYRO_CONFIG::FS_SEL));
+        let acc_raw = acc * arange.sensitivity();
+        let gyro_raw = gyro * grange.sensitivity() / crate::PI_180;
+
+        // a new accel sample only once per accel period, the register holds the last one meanwhile
+        let acc_period = tick / (BASE_RATE / ACCEL_RATE);
+        if self.acc_period != Some(acc_period) {
+            self.acc_period = Some(acc_period);
+            self.mock.set_acc_raw([saturate(acc_raw.x), saturate(acc_raw.y), saturate(acc_raw.z)]);
+        }
+        self.mock.set_gyro_raw([saturate(gyro_raw.x), saturate(gyro_raw.y), saturate(gyro_raw.z)]);
+        let variant = ChipVariant::from_who_am_i(self.mock.register(WHOAMI)).unwrap_or(ChipVariant::Mpu6050);
+        self.mock.set_temp_raw(saturate((e.temperature - variant.temp_offset()) * variant.temp_sensitivity()));
+
+        let status = self.mock.register(INT_STATUS::ADDR) | (1 << INT_STATUS::DATA_RDY_INT);
+        self.mock.set_int_status(status);
+    }
+
+    /// First order low pass with bandwidth in Hz, sampled every period s
+    fn low_pass(state: &mut Option<Vector3<f32>>, input: Vector3<f32>, bandwidth: Option<f32>, period: f32) -> Vector3<f32> {
+        let output = match (bandwidth, *state) {
+            (Some(bw), Some(previous)) => {
+                let rc = 1. / (2. * crate::PI * bw);
+                previous + (input - previous) * (period / (period + rc))
+            }
+            _ => input,
+        };
+        *state = Some(output);
+        output
+    }
+}
+
+fn bits_of(byte: u8, block: &BitBlock) -> u8 {
+    crate::bits::get_bits(byte, block.bit, block.length)
+}
+
+fn saturate(value: f32) -> i16 {
+    libm::roundf(value).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+impl<T> ErrorType for Simulator<T> {
+    type Error = MockError;
+}
+
+impl<T: Trajectory> I2c for Simulator<T> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        if let Some(dt) = self.auto_advance {
+            self.advance(dt);
+        }
+        self.mock.transaction(address, operations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mpu6050;
+    use core::cell::Cell;
+
+    #[test]
+    fn simulator_scaling_test() {
+        let roll = UnitQuaternion::from_euler_angles(crate::PI / 2., 0., 0.);
+        let trajectory = move |_t: f32| MotionState {
+            orientation: roll,
+            angular_velocity: Vector3::new(0.5, 0., 0.),
+            acceleration: Vector3::new(0., 0., 3. * STANDARD_GRAVITY),
+        };
+        let errors = SensorErrors { gyro_bias: Vector3::new(0., 0.1, 0.), temperature: 40., ..SensorErrors::default() };
+        let mut sim = Simulator::new(trajectory, errors);
+        // a fresh sample with every transaction, so range changes apply to the next read
+        sim.set_auto_advance(Some(0.001));
+
+        let mut mpu = Mpu6050::new(&mut sim);
+        mpu.set_accel_range(crate::AccelRange::G8).unwrap();
+        mpu.set_gyro_range(crate::GyroRange::D500).unwrap();
+        // 4 g upwards in world: along sensor y after rolling by 90°
+        assert!((mpu.get_acc().unwrap() - Vector3::new(0., 4., 0.)).amax() < 1e-3);
+        assert!((mpu.get_gyro().unwrap() - Vector3::new(0.5, 0.1, 0.)).amax() < 1e-3);
+        assert!((mpu.get_temp().unwrap() - 40.).abs() < 1e-2);
+
+        // G2 saturates
+        mpu.set_accel_range(crate::AccelRange::G2).unwrap();
+        assert!((mpu.get_acc().unwrap().y - 32767. / 16384.).abs() < 1e-3);
+
+        // temperature scaled for the part in WHO_AM_I
+        let mut mock = MockMpu6050::new();
+        mock.set_who_am_i(0x70);
+        let mut sim = Simulator::new_with_mock(mock, trajectory, errors);
+        sim.advance(0.001);
+        assert_eq!(sim.mock().register(TEMP_OUT_H), ((19. * 333.87) as i16 >> 8) as u8);
+        let mut mpu = Mpu6050::new(&mut sim);
+        mpu.detect_variant().unwrap();
+        assert!((mpu.get_temp().unwrap() - 40.).abs() < 1e-2);
+    }
+
+    #[test]
+    fn simulator_sample_rate_test() {
+        let t_seen = Cell::new(-1.);
+        let trajectory = |t: f32| {
+            t_seen.set(t);
+            MotionState::at_rest(UnitQuaternion::identity())
+        };
+        let mut sim = Simulator::new(trajectory, SensorErrors { acc_noise: 0.01, ..SensorErrors::default() });
+        // DLPF 3 -> 1 kHz, SMPLRT_DIV 9 -> 100 Hz
+        sim.mock_mut().set_register(CONFIG::ADDR, 3);
+        sim.mock_mut().set_register(SMPLRT_DIV, 9);
+        assert_eq!(sim.sample_rate(), 100.);
+
+        sim.advance(0.005);
+        let first = sim.mock().register(ACC_REGX_H + 1);
+        assert_eq!(t_seen.get(), 0.);
+        sim.advance(0.004);
+        assert_eq!(sim.mock().register(ACC_REGX_H + 1), first);
+        assert_eq!(t_seen.get(), 0.);
+        sim.advance(0.002);
+        assert_ne!(sim.mock().register(ACC_REGX_H + 1), first);
+        assert_eq!(t_seen.get(), 0.01);
+    }
+
+    #[test]
+    fn simulator_timing_test() {
+        let t_seen = Cell::new(0.);
+        let trajectory = |t: f32| {
+            t_seen.set(t);
+            MotionState {
+                orientation: UnitQuaternion::identity(),
+                angular_velocity: Vector3::new(t, 0., 0.),
+                acceleration: Vector3::new(t * STANDARD_GRAVITY, 0., 0.),
+            }
+        };
+        let mut sim = Simulator::new(trajectory, SensorErrors::default());
+
+        // DLPF off: gyro at 8 kHz, accel at 1 kHz
+        sim.mock_mut().set_register(CONFIG::ADDR, 0);
+        assert_eq!(sim.sample_rate(), 8000.);
+        sim.advance(0.0001);
+        let (acc, gyro) = (sim.mock().register(ACC_REGX_H + 1), sim.mock().register(GYRO_REGX_H + 1));
+        for _ in 0..7 {
+            sim.advance(0.000125);
+            assert_ne!(sim.mock().register(GYRO_REGX_H + 1), gyro);
+            assert_eq!(sim.mock().register(ACC_REGX_H + 1), acc);
+        }
+        sim.advance(0.000125);
+        assert_ne!(sim.mock().register(ACC_REGX_H + 1), acc);
+
+        // sample times don't drift over 1000 s at 100 Hz
+        let mut sim = Simulator::new(trajectory, SensorErrors::default());
+        sim.mock_mut().set_register(CONFIG::ADDR, 3);
+        sim.mock_mut().set_register(SMPLRT_DIV, 9);
+        for _ in 0..1000 {
+            sim.advance(1.);
+        }
+        assert_eq!(t_seen.get(), 1000.);
+    }
+}
@@ -0,0 +1,410 @@
+//! Record and replay of bus traffic.
+//!
+//! `Recorder` wraps any `I2c` and reports every operation the driver issues, with a timestamp,
+//! to a sink. `Replay` is an `I2c` that feeds such a log back to the driver: writes are checked
+//! against the log, reads return the logged data and logged bus errors are raised again. A capture
+//! from the field can thus be run through the exact same driver code on a desktop.
+//!
+//! Records print as one line each and parse back with `Record::parse`:
+//! ```text
+//! <timestamp µs> <address> <op> <data>
+//! 1000 68 W 3b        first operation of a transaction: W(rite) or R(ead)
+//! 1000 68 r 0000ff    following operations: w or r
+//! 1250 68 E bus 3b    failed transaction: bus, arbitration, nack, nack-address, nack-data, overrun,
+//!                     other; then the bytes written by its first operation
+//! ```
+//! Address and data are hex. Only the first `MAX_DATA` bytes of an operation are stored, enough for
+//! a burst over the whole register map. Read the FIFO in chunks of at most `MAX_DATA` bytes to keep
+//! a capture replayable.
+
+use core::fmt;
+use core::str::FromStr;
+use embedded_hal::i2c::{Error, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+/// Bytes of payload stored per record, covers a burst over all 128 registers
+pub const MAX_DATA: usize = 128;
+
+/// Type of a recorded operation
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RecordKind {
+    /// Bytes written
+    Write,
+    /// Bytes read
+    Read,
+    /// Transaction failed, data is what its first operation wrote, if it was a write
+    Failed(ErrorKind),
+}
+
+/// One logged bus operation
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    /// Time of the transaction start in µs, from the recorder's clock
+    pub timestamp_us: u64,
+    /// 7 bit slave address
+    pub address: u8,
+    /// Operation
+    pub kind: RecordKind,
+    /// First operation of a transaction
+    pub start: bool,
+    len: usize,
+    data: [u8; MAX_DATA],
+}
+
+impl Record {
+    /// New record, data beyond `MAX_DATA` bytes is dropped (`len` still reports the full length)
+    pub fn new(timestamp_us: u64, address: u8, kind: RecordKind, start: bool, data: &[u8]) -> Self {
+        let mut stored = [0; MAX_DATA];
+        let n = data.len().min(MAX_DATA);
+        stored[..n].copy_from_slice(&data[..n]);
+        Record {
+            timestamp_us,
+            address,
+            kind,
+            start,
+            len: data.len(),
+            data: stored,
+        }
+    }
+
+    /// Length of the operation in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the operation transferred no data
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the payload was longer than `MAX_DATA`
+    pub fn is_truncated(&self) -> bool {
+        self.len > MAX_DATA
+    }
+
+    /// Stored payload
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len.min(MAX_DATA)]
+    }
+
+    /// Parse a line in the format written by `Display`, None if malformed
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let timestamp_us = fields.next()?.parse().ok()?;
+        let address = u8::from_str_radix(fields.next()?, 16).ok()?;
+        let (kind, start) = match fields.next()? {
+            "W" => (RecordKind::Write, true),
+            "w" => (RecordKind::Write, false),
+            "R" => (RecordKind::Read, true),
+            "r" => (RecordKind::Read, false),
+            "E" => (RecordKind::Failed(error_kind(fields.next()?)?), true),
+            _ => return None,
+        };
+        let payload = fields.next().unwrap_or("");
+        if fields.next().is_some() {
+            return None;
+        }
+
+        let n = payload.len() / 2;
+        if payload.len() != 2 * n || n > MAX_DATA {
+            return None;
+        }
+        let mut data = [0; MAX_DATA];
+        for (i, byte) in data.iter_mut().take(n).enumerate() {
+            *byte = u8::from_str_radix(payload.get(2 * i..2 * i + 2)?, 16).ok()?;
+        }
+        Some(Record::new(timestamp_us, address, kind, start, &data[..n]))
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:02x} ", self.timestamp_us, self.address)?;
+        match (self.kind, self.start) {
+            (RecordKind::Failed(kind), _) => {
+                write!(f, "E {}", error_name(kind))?;
+                if !self.is_empty() {
+                    write!(f, " ")?;
+                }
+            }
+            (RecordKind::Write, true) => write!(f, "W ")?,
+            (RecordKind::Write, false) => write!(f, "w ")?,
+            (RecordKind::Read, true) => write!(f, "R ")?,
+            (RecordKind::Read, false) => write!(f, "r ")?,
+        }
+        for byte in self.data() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Record {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Record::parse(s).ok_or(())
+    }
+}
+
+fn error_name(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Bus => "bus",
+        ErrorKind::ArbitrationLoss => "arbitration",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => "nack-address",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => "nack-data",
+        ErrorKind::NoAcknowledge(_) => "nack",
+        ErrorKind::Overrun => "overrun",
+        _ => "other",
+    }
+}
+
+fn error_kind(name: &str) -> Option<ErrorKind> {
+    Some(match name {
+        "bus" => ErrorKind::Bus,
+        "arbitration" => ErrorKind::ArbitrationLoss,
+        "nack-address" => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+        "nack-data" => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+        "nack" => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+        "overrun" => ErrorKind::Overrun,
+        "other" => ErrorKind::Other,
+        _ => return None,
+    })
+}
+
+/// `I2c` wrapper logging every operation to `sink`, timestamped with `clock` (µs)
+pub struct Recorder<I2C, C, S> {
+    i2c: I2C,
+    clock: C,
+    sink: S,
+}
+
+impl<I2C, C, S> Recorder<I2C, C, S>
+where
+    I2C: I2c,
+    C: FnMut() -> u64,
+    S: FnMut(&Record),
+{
+    /// Wrap i2c. `clock` returns the current time in µs, `sink` receives the records, e.g. to
+    /// push them to a buffer or print them
+    pub fn new(i2c: I2C, clock: C, sink: S) -> Self {
+        Recorder { i2c, clock, sink }
+    }
+
+    /// Wrapped bus
+    pub fn inner(&mut self) -> &mut I2C {
+        &mut self.i2c
+    }
+
+    /// Unwrap the bus
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: I2c, C, S> ErrorType for Recorder<I2C, C, S> {
+    type Error = I2C::Error;
+}
+
+impl<I2C, C, S> I2c for Recorder<I2C, C, S>
+where
+    I2C: I2c,
+    C: FnMut() -> u64,
+    S: FnMut(&Record),
+{
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        let timestamp = (self.clock)();
+        if let Err(e) = self.i2c.transaction(address, operations) {
+            let written = match operations.first() {
+                Some(Operation::Write(bytes)) => bytes,
+                _ => &[][..],
+            };
+            (self.sink)(&Record::new(timestamp, address, RecordKind::Failed(e.kind()), true, written));
+            return Err(e);
+        }
+
+        for (i, operation) in operations.iter().enumerate() {
+            let record = match operation {
+                Operation::Write(bytes) => Record::new(timestamp, address, RecordKind::Write, i == 0, bytes),
+                Operation::Read(buf) => Record::new(timestamp, address, RecordKind::Read, i == 0, buf),
+            };
+            (self.sink)(&record);
+        }
+        Ok(())
+    }
+}
+
+/// Errors of `Replay`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReplayError {
+    /// The logged transaction failed with this error
+    Recorded(ErrorKind),
+    /// The driver issued a different operation than logged at this record index
+    Mismatch(usize),
+    /// No records left
+    Exhausted,
+}
+
+impl Error for ReplayError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ReplayError::Recorded(kind) => *kind,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// `I2c` answering from a log of `Record`s
+#[derive(Clone, Debug)]
+pub struct Replay<'a> {
+    records: &'a [Record],
+    position: usize,
+}
+
+impl<'a> Replay<'a> {
+    /// Replay records from the start
+    pub fn new(records: &'a [Record]) -> Self {
+        Replay { records, position: 0 }
+    }
+
+    /// Index of the next record
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Timestamp of the next record, i.e. the capture time of the next transaction
+    pub fn timestamp_us(&self) -> Option<u64> {
+        self.records.get(self.position).map(|r| r.timestamp_us)
+    }
+
+    /// Whether all records were consumed
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.records.len()
+    }
+}
+
+impl ErrorType for Replay<'_> {
+    type Error = ReplayError;
+}
+
+impl I2c for Replay<'_> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        let first = *self.records.get(self.position).ok_or(ReplayError::Exhausted)?;
+        if first.address != address || !first.start {
+            return Err(ReplayError::Mismatch(self.position));
+        }
+        if let RecordKind::Failed(kind) = first.kind {
+            let written = match operations.first() {
+                Some(Operation::Write(bytes)) => bytes,
+                _ => &[][..],
+            };
+            if first.is_truncated() || written != first.data() {
+                return Err(ReplayError::Mismatch(self.position));
+            }
+            self.position += 1;
+            return Err(ReplayError::Recorded(kind));
+        }
+
+        for (i, operation) in operations.iter_mut().enumerate() {
+            let index = self.position + i;
+            let record = self.records.get(index).ok_or(ReplayError::Exhausted)?;
+            if record.address != address || record.start != (i == 0) || record.is_truncated() {
+                return Err(ReplayError::Mismatch(index));
+            }
+            match (operation, record.kind) {
+                (Operation::Write(bytes), RecordKind::Write) if *bytes == record.data() => {}
+                (Operation::Read(buf), RecordKind::Read) if buf.len() == record.len() => {
+                    buf.copy_from_slice(record.data());
+                }
+                _ => return Err(ReplayError::Mismatch(index)),
+            }
+        }
+
+        // the logged transaction must not have had more operations
+        let end = self.position + operations.len();
+        if self.records.get(end).is_some_and(|r| !r.start && r.address == address) {
+            return Err(ReplayError::Mismatch(end));
+        }
+        self.position = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMpu6050;
+    use crate::Mpu6050;
+    extern crate std;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    #[test]
+    fn record_replay_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([16384, -8192, 0]);
+        mock.set_temp_raw(340);
+
+        let mut log = Vec::new();
+        let mut t = 0;
+        {
+            let clock = || { t += 100; t };
+            let mut mpu = Mpu6050::new(Recorder::new(mock, clock, |r: &Record| log.push(r.to_string())));
+            mpu.set_accel_range(crate::AccelRange::G2).unwrap();
+            mpu.get_acc().unwrap();
+            mpu.get_temp().unwrap();
+        }
+        assert_eq!(log[0], "100 68 W 1c");
+        assert_eq!(log[1], "100 68 r 00");
+
+        // round trip through text, then the same calls replay the same results
+        let records: Vec<Record> = log.iter().map(|l| l.parse().unwrap()).collect();
+        let mut mpu = Mpu6050::new(Replay::new(&records));
+        mpu.set_accel_range(crate::AccelRange::G2).unwrap();
+        let acc = mpu.get_acc().unwrap();
+        assert_eq!((acc.x, acc.y), (1., -0.5));
+        assert!((mpu.get_temp().unwrap() - 37.53).abs() < 1e-3);
+        assert!(matches!(mpu.get_temp(), Err(crate::Mpu6050Error::I2c(ReplayError::Exhausted))));
+
+        // diverging driver calls are detected
+        let mut mpu = Mpu6050::new(Replay::new(&records));
+        assert!(matches!(mpu.get_gyro(), Err(crate::Mpu6050Error::I2c(ReplayError::Mismatch(0)))));
+
+        // logged failures are raised again
+        let failed = Record::parse("5 68 E nack-data").unwrap();
+        assert_eq!(failed.to_string(), "5 68 E nack-data");
+        let records = [failed];
+        let mut mpu = Mpu6050::new(Replay::new(&records));
+        assert!(matches!(mpu.get_temp(), Err(crate::Mpu6050Error::I2c(ReplayError::Mismatch(0)))));
+        let failed = Record::parse("5 68 E nack-data 41").unwrap();
+        assert_eq!(failed.to_string(), "5 68 E nack-data 41");
+        let records = [failed];
+        let mut mpu = Mpu6050::new(Replay::new(&records));
+        assert!(matches!(mpu.get_temp(),
+            Err(crate::Mpu6050Error::I2c(ReplayError::Recorded(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data))))));
+    }
+
+    #[test]
+    fn register_dump_replay_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([100, 200, 300]);
+
+        let mut records = Vec::new();
+        let dump = {
+            let mut mpu = Mpu6050::new(Recorder::new(mock, || 0, |r: &Record| records.push(*r)));
+            mpu.set_dlpf(crate::device::DLPF_CFG::_44).unwrap();
+            // failed transactions keep the register they were addressed to
+            mpu.i2c.inner().fail_next(1);
+            assert!(mpu.get_temp().is_err());
+            mpu.dump_registers().unwrap()
+        };
+        assert!(records.iter().all(|r| !r.is_truncated()));
+        let failed = records.iter().find(|r| matches!(r.kind, RecordKind::Failed(_))).unwrap();
+        assert_eq!(failed.to_string(), "0 68 E bus 41");
+
+        let mut mpu = Mpu6050::new(Replay::new(&records));
+        mpu.set_dlpf(crate::device::DLPF_CFG::_44).unwrap();
+        assert!(matches!(mpu.get_temp(), Err(crate::Mpu6050Error::I2c(ReplayError::Recorded(ErrorKind::Bus)))));
+        assert_eq!(mpu.dump_registers().unwrap(), dump);
+        assert!(mpu.i2c.is_finished());
+    }
+}
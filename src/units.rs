@@ -0,0 +1,108 @@
+//! Strongly typed readings, backed by [`uom`](https://docs.rs/uom).
+//!
+//! Enabled with the `units` feature. The plain `get_acc`, `get_gyro` and `get_temp` readouts
+//! return bare `f32`s in g, rad/s and °C; the `*_si` variants below return quantities instead,
+//! so mixing up e.g. degrees and radians becomes a compile error.
+//!
+//! ```no_run
+//! # #[cfg(feature = "mock")] {
+//! use mpu6050::{Mpu6050, mock::MockMpu6050, units::*};
+//!
+//! let mut mpu = Mpu6050::new(MockMpu6050::new());
+//! let gyro = mpu.get_gyro_si().unwrap();
+//! let deg_s = to_degrees_per_second(&gyro);
+//! let temp_f = to_fahrenheit(mpu.get_temp_si().unwrap());
+//! # }
+//! ```
+
+use crate::power::AccelAvailable;
+use crate::{Mpu6050, Mpu6050Error};
+use embedded_hal::i2c::I2c;
+use nalgebra::Vector3;
+
+pub use uom::si::f32::{Acceleration, AngularVelocity, ThermodynamicTemperature};
+pub use uom::si::acceleration::{meter_per_second_squared, standard_gravity};
+pub use uom::si::angular_velocity::{degree_per_second, radian_per_second};
+pub use uom::si::thermodynamic_temperature::{degree_celsius, degree_fahrenheit, kelvin};
+
+/// Converts an accelerometer reading in g to a typed acceleration
+pub fn acceleration_from_g(acc: &Vector3<f32>) -> Vector3<Acceleration> {
+    acc.map(Acceleration::new::<standard_gravity>)
+}
+
+/// Converts a gyro reading in rad/s to a typed angular velocity
+pub fn angular_velocity_from_rad(gyro: &Vector3<f32>) -> Vector3<AngularVelocity> {
+    gyro.map(AngularVelocity::new::<radian_per_second>)
+}
+
+/// Converts a typed acceleration to m/s²
+pub fn to_meters_per_second_squared(acc: &Vector3<Acceleration>) -> Vector3<f32> {
+    acc.map(|a| a.get::<meter_per_second_squared>())
+}
+
+/// Converts a typed angular velocity to deg/s
+pub fn to_degrees_per_second(gyro: &Vector3<AngularVelocity>) -> Vector3<f32> {
+    gyro.map(|g| g.get::<degree_per_second>())
+}
+
+/// Converts a typed temperature to °F
+pub fn to_fahrenheit(temp: ThermodynamicTemperature) -> f32 {
+    temp.get::<degree_fahrenheit>()
+}
+
+impl<I2C, E, MODE> Mpu6050<I2C, MODE>
+where
+    I2C: I2c<Error=E>,
+    MODE: AccelAvailable,
+{
+    /// Accelerometer readings as typed acceleration, see `get_acc`
+    pub fn get_acc_si(&mut self) -> Result<Vector3<Acceleration>, Mpu6050Error<E>> {
+        Ok(acceleration_from_g(&self.get_acc()?))
+    }
+}
+
+impl<I2C, E> Mpu6050<I2C>
+where
+    I2C: I2c<Error=E>,
+{
+    /// Gyro readings as typed angular velocity, see `get_gyro`
+    pub fn get_gyro_si(&mut self) -> Result<Vector3<AngularVelocity>, Mpu6050Error<E>> {
+        Ok(angular_velocity_from_rad(&self.get_gyro()?))
+    }
+
+    /// Sensor temp as typed thermodynamic temperature, see `get_temp`
+    pub fn get_temp_si(&mut self) -> Result<ThermodynamicTemperature, Mpu6050Error<E>> {
+        Ok(ThermodynamicTemperature::new::<degree_celsius>(self.get_temp()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::LP_WAKE_CTRL;
+    use crate::mock::MockMpu6050;
+
+    #[test]
+    fn conversions_test() {
+        let acc = acceleration_from_g(&Vector3::new(0., 0., 1.));
+        let ms2 = to_meters_per_second_squared(&acc);
+        assert!((ms2.z - 9.80665).abs() < 1e-4);
+
+        let gyro = angular_velocity_from_rad(&Vector3::new(crate::PI, 0., 0.));
+        let deg_s = to_degrees_per_second(&gyro);
+        assert!((deg_s.x - 180.).abs() < 1e-3);
+
+        let temp = ThermodynamicTemperature::new::<degree_celsius>(100.);
+        assert!((to_fahrenheit(temp) - 212.).abs() < 1e-2);
+    }
+
+    #[test]
+    fn low_power_acc_si_test() {
+        let mut mock = MockMpu6050::new();
+        mock.set_acc_raw([0, 0, 16384]);
+        let mpu = Mpu6050::new(mock);
+        let mut mpu = mpu.into_low_power_accel(LP_WAKE_CTRL::_5).map_err(|e| e.error).unwrap();
+        let acc = to_meters_per_second_squared(&mpu.get_acc_si().unwrap());
+        assert!((acc.z - 9.80665).abs() < 1e-4);
+    }
+}